@@ -0,0 +1,283 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::Cursor,
+    path::PathBuf,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, RwLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use rodio::{source::Source, Decoder, OutputStream};
+use serde::{Deserialize, Serialize};
+
+use crate::{channel::MessageSender, enums::SoundKind};
+
+/// Sound played for every kind the sound pack does not replace.
+const DEFAULT_SOUND: &[u8] = include_bytes!("../assets/notif_sound.wav");
+
+/// File extensions looked up in the pack directory, in order.
+const EXTENSIONS: [&str; 3] = ["wav", "ogg", "flac"];
+
+/// Sounds requested within this window of the last one are skipped, so a
+/// burst of events plays a single sound.
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Sound options of the config file, edited from the sound settings window.
+///
+/// A sound pack is a directory of WAV/OGG/FLAC files replacing the default
+/// sound. Kinds missing from `files` and `default_sounds` use `<kind>.wav`,
+/// `<kind>.ogg` or `<kind>.flac` from the directory when present, the default
+/// sound otherwise.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SoundSettings {
+    pub muted: bool,
+    /// Master volume, `1.0` plays the files as they are.
+    pub volume: f64,
+    /// Volume per kind on top of the master volume, `1.0` when missing.
+    pub volumes: HashMap<SoundKind, f64>,
+
+    pub dir: Option<PathBuf>,
+    /// File per kind, relative paths are resolved against `dir`.
+    pub files: HashMap<SoundKind, PathBuf>,
+    /// Kinds set to the default sound, even when the pack has a file named
+    /// after them.
+    pub default_sounds: HashSet<SoundKind>,
+}
+
+impl Default for SoundSettings {
+    fn default() -> Self {
+        SoundSettings {
+            muted: false,
+            volume: 0.1,
+            volumes: HashMap::new(),
+            dir: None,
+            files: HashMap::new(),
+            default_sounds: HashSet::new(),
+        }
+    }
+}
+
+impl SoundSettings {
+    pub fn kind_volume(&self, kind: SoundKind) -> f64 {
+        self.volumes.get(&kind).copied().unwrap_or(1.0)
+    }
+
+    /// Sound files in the pack directory, sorted by name.
+    pub fn pack_files(&self) -> Vec<PathBuf> {
+        let Some(ref dir) = self.dir else {
+            return vec![];
+        };
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("Error when listing sound pack {}: {}", dir.display(), e);
+                return vec![];
+            }
+        };
+
+        let mut files: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| PathBuf::from(entry.file_name()))
+            .filter(|name| {
+                name.extension()
+                    .and_then(|extension| extension.to_str())
+                    .is_some_and(|extension| EXTENSIONS.contains(&extension))
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    /// File configured or found for `kind`, `None` for the default sound.
+    pub fn file(&self, kind: SoundKind) -> Option<PathBuf> {
+        if self.default_sounds.contains(&kind) {
+            return None;
+        }
+        if let Some(file) = self.files.get(&kind) {
+            return Some(match self.dir {
+                Some(ref dir) => dir.join(file),
+                None => file.clone(),
+            });
+        }
+
+        let dir = self.dir.as_ref()?;
+        EXTENSIONS
+            .iter()
+            .map(|extension| dir.join(format!("{}.{}", kind.key(), extension)))
+            .find(|path| path.is_file())
+    }
+
+    /// Reads the sounds of the pack, checking that each one decodes.
+    fn read_sounds(&self, log_error: impl Fn(String)) -> HashMap<SoundKind, Arc<[u8]>> {
+        let mut sounds = HashMap::new();
+        for kind in SoundKind::ALL {
+            let Some(path) = self.file(kind) else {
+                continue;
+            };
+            let sound: Arc<[u8]> = match fs::read(&path) {
+                Ok(sound) => sound.into(),
+                Err(e) => {
+                    log_error(format!(
+                        "Error when loading sound {}: {}",
+                        path.display(),
+                        e
+                    ));
+                    continue;
+                }
+            };
+            if let Err(e) = Decoder::new(Cursor::new(sound.clone())) {
+                log_error(format!(
+                    "Error when decoding sound {}: {}",
+                    path.display(),
+                    e
+                ));
+                continue;
+            }
+            sounds.insert(kind, sound);
+        }
+        sounds
+    }
+}
+
+enum PlayRequest {
+    Sound(SoundKind),
+    /// Plays regardless of the mute toggle and the debounce.
+    Preview(SoundKind),
+    /// Reads the sound files again after the settings changed.
+    Reload,
+}
+
+/// Handle to the audio thread.
+///
+/// The thread keeps one output stream open for the whole process, sending a
+/// request never blocks the caller. Volumes and the mute toggle are read from
+/// `settings` for every sound.
+#[derive(Clone)]
+pub struct AudioPlayer {
+    tx: Sender<PlayRequest>,
+    pub settings: Arc<RwLock<SoundSettings>>,
+}
+
+impl AudioPlayer {
+    /// Starts the audio thread, logging its errors to `errors` when given,
+    /// to stderr otherwise.
+    pub fn spawn(settings: SoundSettings, errors: Option<MessageSender>) -> Self {
+        let settings = Arc::new(RwLock::new(settings));
+        let (tx, rx) = mpsc::channel();
+        let thread_settings = settings.clone();
+        thread::Builder::new()
+            .name("audio".to_owned())
+            .spawn(move || run(rx, thread_settings, errors))
+            .expect("Unable to spawn audio thread");
+
+        AudioPlayer { tx, settings }
+    }
+
+    pub fn play(&self, kind: SoundKind) {
+        // The thread is gone only if there is no sound device
+        let _ = self.tx.send(PlayRequest::Sound(kind));
+    }
+
+    pub fn preview(&self, kind: SoundKind) {
+        let _ = self.tx.send(PlayRequest::Preview(kind));
+    }
+
+    pub fn reload(&self) {
+        let _ = self.tx.send(PlayRequest::Reload);
+    }
+}
+
+fn run(
+    rx: Receiver<PlayRequest>,
+    settings: Arc<RwLock<SoundSettings>>,
+    errors: Option<MessageSender>,
+) {
+    let log_error = |message: String| match errors {
+        Some(ref errors) => errors.log_error(message),
+        None => eprintln!("{}", message),
+    };
+
+    // Get a output stream handle to the default physical sound device
+    let (_stream, stream_handle) = match OutputStream::try_default() {
+        Ok(output) => output,
+        Err(e) => {
+            log_error(format!("Error when opening sound device: {}", e));
+            return;
+        }
+    };
+    let default_sound: Arc<[u8]> = DEFAULT_SOUND.into();
+    let mut sounds = settings.read().unwrap().read_sounds(log_error);
+
+    let mut last_played: Option<Instant> = None;
+    for request in rx {
+        let kind = match request {
+            PlayRequest::Sound(kind) => {
+                if settings.read().unwrap().muted
+                    || last_played.is_some_and(|played| played.elapsed() < DEBOUNCE)
+                {
+                    continue;
+                }
+                kind
+            }
+            PlayRequest::Preview(kind) => kind,
+            PlayRequest::Reload => {
+                sounds = settings.read().unwrap().read_sounds(log_error);
+                continue;
+            }
+        };
+
+        let sound = sounds.get(&kind).unwrap_or(&default_sound).clone();
+        // Decode that sound file into a source, the format is guessed
+        let source = match Decoder::new(Cursor::new(sound)) {
+            Ok(source) => source,
+            Err(e) => {
+                log_error(format!("Error when decoding sound: {}", e));
+                continue;
+            }
+        };
+        let volume = {
+            let settings = settings.read().unwrap();
+            settings.volume * settings.kind_volume(kind)
+        };
+        // Scale the volume by multiplying each sample by a factor
+        let source = source.amplify(volume as f32);
+        // Overlapping sounds are mixed by the output stream
+        match stream_handle.play_raw(source.convert_samples()) {
+            Ok(_) => last_played = Some(Instant::now()),
+            Err(e) => log_error(format!("Error when playing sound: {}", e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    #[test]
+    fn default_choice_wins_over_the_pack() {
+        let dir = env::temp_dir().join(format!("discord_watcher_sounds_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let join = dir.join(format!("{}.wav", SoundKind::Join.key()));
+        fs::write(&join, b"").unwrap();
+
+        let mut settings = SoundSettings {
+            dir: Some(dir.clone()),
+            ..SoundSettings::default()
+        };
+        let found = settings.file(SoundKind::Join);
+        settings.default_sounds.insert(SoundKind::Join);
+        let chosen = settings.file(SoundKind::Join);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(found, Some(join));
+        assert_eq!(chosen, None);
+        assert_eq!(settings.file(SoundKind::Leave), None);
+    }
+}
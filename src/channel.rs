@@ -0,0 +1,125 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::{
+    sync::mpsc::{
+        self,
+        error::{TryRecvError, TrySendError},
+        Receiver, Sender,
+    },
+    time,
+};
+
+use crate::enums::ChannelMessage;
+
+/// Messages waiting for the UI before new ones are dropped.
+pub const CAPACITY: usize = 1024;
+
+/// How often [`MessageSender::wait_for_room`] checks the queue.
+const ROOM_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The UI stopped receiving messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Closed;
+
+/// Creates the channel from the Discord handler to a frontend, repainting
+/// `ctx` on every message when there is one.
+pub fn message_channel(ctx: Option<egui::Context>) -> (MessageSender, MessageReceiver) {
+    let (tx, rx) = mpsc::channel(CAPACITY);
+    let dropped = Arc::new(AtomicUsize::new(0));
+    (
+        MessageSender {
+            tx,
+            ctx,
+            dropped: dropped.clone(),
+            on_terminal: false,
+        },
+        MessageReceiver { rx, dropped },
+    )
+}
+
+/// Sending side, used by the Discord handler. It never waits for the UI: a
+/// message that doesn't fit in the queue is dropped and counted, the
+/// frontend then tells how many were lost.
+#[derive(Clone)]
+pub struct MessageSender {
+    tx: Sender<ChannelMessage>,
+    ctx: Option<egui::Context>,
+    dropped: Arc<AtomicUsize>,
+    /// The frontend draws on the terminal, which stderr would scribble over.
+    on_terminal: bool,
+}
+
+impl MessageSender {
+    /// Sends the errors logged from now on to the frontend instead of stderr,
+    /// for frontends drawing on the terminal.
+    pub fn on_terminal(mut self) -> Self {
+        self.on_terminal = true;
+        self
+    }
+
+    pub fn send(&self, message: ChannelMessage) -> Result<(), Closed> {
+        match self.tx.try_send(message) {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(TrySendError::Closed(_)) => return Err(Closed),
+        }
+        if let Some(ref ctx) = self.ctx {
+            ctx.request_repaint();
+        }
+        Ok(())
+    }
+
+    /// Logs `message` to stderr, or shows it as an error when the frontend
+    /// draws on the terminal.
+    pub fn log_error(&self, message: String) {
+        // Once the frontend is gone the terminal is usable again
+        if !self.on_terminal || self.send(ChannelMessage::Error(message.clone())).is_err() {
+            eprintln!("{}", message);
+        }
+    }
+
+    /// Shows `message` as an error in the frontend, also logging it to stderr
+    /// unless the frontend draws on the terminal.
+    pub fn show_error(&self, message: String) {
+        if !self.on_terminal {
+            eprintln!("{}", message);
+        }
+        let _ = self.send(ChannelMessage::Error(message));
+    }
+
+    /// Waits until `room` more messages fit in the queue, or the UI is gone.
+    /// For senders that can be slowed down, like replays.
+    pub async fn wait_for_room(&self, room: usize) {
+        while self.tx.capacity() < room.min(CAPACITY) && !self.tx.is_closed() {
+            time::sleep(ROOM_POLL_INTERVAL).await;
+        }
+    }
+}
+
+pub struct MessageReceiver {
+    rx: Receiver<ChannelMessage>,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl MessageReceiver {
+    pub fn try_recv(&mut self) -> Result<ChannelMessage, TryRecvError> {
+        self.rx.try_recv()
+    }
+
+    pub async fn recv(&mut self) -> Option<ChannelMessage> {
+        self.rx.recv().await
+    }
+
+    /// Number of messages dropped since the last call.
+    pub fn take_dropped(&self) -> usize {
+        self.dropped.swap(0, Ordering::Relaxed)
+    }
+}
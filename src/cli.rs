@@ -0,0 +1,139 @@
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+
+use crate::{
+    config::{Config, Overrides},
+    enums::{ExportFormat, VoiceEventKind},
+    export::parse_date,
+};
+
+pub const USAGE: &str = "\
+Usage: discord_watcher [egui|tui|daemon] [options]
+       discord_watcher replay <file> [--fast] [--headless] [options]
+       discord_watcher export <file> [--format csv|jsonl|md] [--from <date>] [--to <date>]
+                              [--user <user>] [--channel <channel>] [--kind <kind>] [--all]
+
+Options:
+    --config <file>       Read and save the settings in <file>
+    --token-file <file>   Read the bot token from <file>
+    --mute                Mute all sounds
+    --no-toasts           Disable desktop notifications
+    --always-on-top       Keep the window above the others
+    --custom-frame        Draw the window without system decorations
+    --debug               Open the debug window at startup
+    --record <file>       Append the voice related gateway payloads to <file>, normalised
+                          by serenity rather than raw
+    --fast                Replay without waiting between payloads
+    --headless            Replay to stdout instead of the window
+    --format <format>     Export format, guessed from the file extension by default
+    --from <date>         Export events from <date> (YYYY-MM-DD)
+    --to <date>           Export events up to <date> included
+    --user <user>         Export the events of <user>, by name or ID, repeatable
+    --channel <channel>   Export the events in <channel>, by name or ID, repeatable
+    --kind <kind>         Export the <kind> events (joined, left, moved, muted...), repeatable
+    --all                 Export every guild and event category, not only the watched ones
+    -h, --help            Show this help";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Command {
+    #[default]
+    Egui,
+    Tui,
+    Daemon,
+    /// Feeds a file written with `--record` to the handler.
+    Replay(PathBuf),
+    /// Writes the event history to a file.
+    Export(PathBuf),
+    Gtk,
+    Help,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Cli {
+    pub command: Command,
+    pub config_path: Option<PathBuf>,
+    pub overrides: Overrides,
+    /// Options of the `replay` command.
+    pub fast: bool,
+    pub headless: bool,
+    /// Options of the `export` command.
+    pub format: Option<ExportFormat>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub users: Vec<String>,
+    pub channels: Vec<String>,
+    pub kinds: Vec<VoiceEventKind>,
+    pub all: bool,
+}
+
+impl Cli {
+    /// Parses the arguments following the program name.
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut cli = Cli::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "egui" => cli.command = Command::Egui,
+                "tui" => cli.command = Command::Tui,
+                "daemon" => cli.command = Command::Daemon,
+                "replay" => cli.command = Command::Replay(path_value(&arg, args.next())?),
+                "export" => cli.command = Command::Export(path_value(&arg, args.next())?),
+                "gtk" => cli.command = Command::Gtk,
+                "-h" | "--help" => cli.command = Command::Help,
+                "--config" => cli.config_path = Some(path_value(&arg, args.next())?),
+                "--token-file" => cli.overrides.token_file = Some(path_value(&arg, args.next())?),
+                "--mute" => cli.overrides.mute = true,
+                "--no-toasts" => cli.overrides.no_toasts = true,
+                "--always-on-top" => cli.overrides.always_on_top = true,
+                "--custom-frame" => cli.overrides.custom_frame = true,
+                "--debug" => cli.overrides.debug = true,
+                "--record" => cli.overrides.record = Some(path_value(&arg, args.next())?),
+                "--fast" => cli.fast = true,
+                "--headless" => cli.headless = true,
+                "--format" => {
+                    let value = flag_value(&arg, args.next(), "a format")?;
+                    cli.format = Some(
+                        ExportFormat::from_key(&value)
+                            .ok_or_else(|| format!("Unknown export format {}", value))?,
+                    );
+                }
+                "--from" => cli.from = Some(parse_date(&flag_value(&arg, args.next(), "a date")?)?),
+                "--to" => cli.to = Some(parse_date(&flag_value(&arg, args.next(), "a date")?)?),
+                "--user" => cli.users.push(flag_value(&arg, args.next(), "a user")?),
+                "--channel" => cli
+                    .channels
+                    .push(flag_value(&arg, args.next(), "a channel")?),
+                "--kind" => {
+                    let value = flag_value(&arg, args.next(), "an event kind")?;
+                    cli.kinds.push(
+                        VoiceEventKind::from_key(&value)
+                            .ok_or_else(|| format!("Unknown event kind {}", value))?,
+                    );
+                }
+                "--all" => cli.all = true,
+                _ => return Err(format!("Unknown argument {}", arg)),
+            }
+        }
+        Ok(cli)
+    }
+
+    /// Loads the config file and applies the overrides to it.
+    pub fn load_config(&self) -> Config {
+        let mut config = Config::load(
+            self.config_path
+                .clone()
+                .unwrap_or_else(Config::default_path),
+        );
+        config.apply_overrides(self.overrides.clone());
+        config
+    }
+}
+
+fn path_value(flag: &str, value: Option<String>) -> Result<PathBuf, String> {
+    flag_value(flag, value, "a file").map(PathBuf::from)
+}
+
+/// The value following `flag`, which expects `what`.
+fn flag_value(flag: &str, value: Option<String>, what: &str) -> Result<String, String> {
+    value.ok_or_else(|| format!("{} expects {}", flag, what))
+}
@@ -0,0 +1,250 @@
+use std::{fs, io, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{audio::SoundSettings, models::WatchFilter, utils::config_dir};
+
+/// Where the bot token is read from.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenSource {
+    /// `DISCORD_TOKEN`, loading `.env` first.
+    #[default]
+    Env,
+    /// A file holding only the token.
+    File(PathBuf),
+    /// The token saved from the token dialog, see [`crate::token::store_token`].
+    Keyring,
+}
+
+/// Desktop notification options, shared between the Discord handler and
+/// the settings window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationSettings {
+    pub enabled: bool,
+    pub timeout_ms: u32,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        NotificationSettings {
+            enabled: true,
+            timeout_ms: 500,
+        }
+    }
+}
+
+/// State of the window toggles at startup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiSettings {
+    pub always_on_top: bool,
+    pub custom_frame: bool,
+    pub roster: bool,
+    pub debug: bool,
+}
+
+impl Default for UiSettings {
+    fn default() -> Self {
+        UiSettings {
+            always_on_top: false,
+            custom_frame: false,
+            roster: true,
+            debug: false,
+        }
+    }
+}
+
+/// Position and inner size of the main window, in points.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Settings stored in `$XDG_CONFIG_HOME/discord_watcher/config.toml`.
+///
+/// Notification rules keep their own file, see [`crate::rules::RuleSet`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub token: TokenSource,
+    /// Restore the window position and size of the last run.
+    pub remember_window: bool,
+    pub window: Option<WindowGeometry>,
+    pub ui: UiSettings,
+    pub filter: WatchFilter,
+    pub notifications: NotificationSettings,
+    pub sounds: SoundSettings,
+
+    /// File the config was loaded from and is saved to.
+    #[serde(skip)]
+    pub path: PathBuf,
+    /// Settings given on the command line for this run only.
+    #[serde(skip)]
+    pub overrides: Overrides,
+    /// The config as read from `path`, before the overrides.
+    #[serde(skip)]
+    file: Option<Box<Config>>,
+    /// Why `path` could not be loaded, it is not overwritten then.
+    #[serde(skip)]
+    load_error: Option<String>,
+}
+
+/// Settings given on the command line, applied on top of the config file
+/// and never saved.
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    pub token_file: Option<PathBuf>,
+    pub mute: bool,
+    pub no_toasts: bool,
+    pub always_on_top: bool,
+    pub custom_frame: bool,
+    pub debug: bool,
+    /// File the gateway payloads are recorded to.
+    pub record: Option<PathBuf>,
+}
+
+impl Config {
+    pub fn default_path() -> PathBuf {
+        config_dir().join("config.toml")
+    }
+
+    /// Loads the config file at `path`, falling back to the defaults when it
+    /// is missing or invalid. An invalid file is left as is by [`Config::save`].
+    pub fn load(path: PathBuf) -> Self {
+        let load_error = match fs::read_to_string(&path) {
+            Ok(content) => match toml::from_str::<Config>(&content) {
+                Ok(config) => {
+                    return Config { path, ..config };
+                }
+                Err(e) => format!("Error when parsing config: {}", e),
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Config {
+                    path,
+                    ..Config::default()
+                };
+            }
+            Err(e) => format!("Error when reading config: {}", e),
+        };
+        eprintln!("{}", load_error);
+        Config {
+            path,
+            load_error: Some(load_error),
+            ..Config::default()
+        }
+    }
+
+    /// Applies `overrides` for this run, keeping the values of the file to
+    /// save them back.
+    pub fn apply_overrides(&mut self, overrides: Overrides) {
+        let file = self.clone();
+        if let Some(ref path) = overrides.token_file {
+            self.token = TokenSource::File(path.clone());
+        }
+        self.sounds.muted |= overrides.mute;
+        self.notifications.enabled &= !overrides.no_toasts;
+        self.ui.always_on_top |= overrides.always_on_top;
+        self.ui.custom_frame |= overrides.custom_frame;
+        self.ui.debug |= overrides.debug;
+        self.overrides = overrides;
+        self.file = Some(Box::new(file));
+    }
+
+    /// The settings to write to the config file. Those still set by an
+    /// override get the value of the file back, those changed since are kept.
+    fn persisted(&self) -> Config {
+        let mut config = self.clone();
+        let (Some(file), overrides) = (&self.file, &self.overrides) else {
+            return config;
+        };
+        if let Some(ref path) = overrides.token_file {
+            if self.token == TokenSource::File(path.clone()) {
+                config.token = file.token.clone();
+            }
+        }
+        if overrides.mute && self.sounds.muted {
+            config.sounds.muted = file.sounds.muted;
+        }
+        if overrides.no_toasts && !self.notifications.enabled {
+            config.notifications.enabled = file.notifications.enabled;
+        }
+        if overrides.always_on_top && self.ui.always_on_top {
+            config.ui.always_on_top = file.ui.always_on_top;
+        }
+        if overrides.custom_frame && self.ui.custom_frame {
+            config.ui.custom_frame = file.ui.custom_frame;
+        }
+        if overrides.debug && self.ui.debug {
+            config.ui.debug = file.ui.debug;
+        }
+        config
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(ref load_error) = self.load_error {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} was not loaded, fix or remove it first ({})",
+                    self.path.display(),
+                    load_error
+                ),
+            ));
+        }
+        let content = toml::to_string_pretty(&self.persisted())
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    #[test]
+    fn does_not_overwrite_an_invalid_file() {
+        let path = env::temp_dir().join(format!("discord_watcher_{}.toml", process::id()));
+        fs::write(&path, "remember_window = maybe\n").unwrap();
+
+        let config = Config::load(path.clone());
+        let saved = config.save();
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(saved.is_err());
+        assert_eq!(content, "remember_window = maybe\n");
+    }
+
+    #[test]
+    fn does_not_save_the_overrides() {
+        let mut config = Config::default();
+        config.apply_overrides(Overrides {
+            token_file: Some(PathBuf::from("token")),
+            mute: true,
+            always_on_top: true,
+            ..Overrides::default()
+        });
+        assert!(config.sounds.muted);
+        assert!(config.ui.always_on_top);
+
+        // Changed since startup, unlike the others
+        config.ui.always_on_top = false;
+        config.ui.roster = false;
+        let persisted = config.persisted();
+
+        assert_eq!(persisted.token, TokenSource::Env);
+        assert!(!persisted.sounds.muted);
+        assert!(!persisted.ui.always_on_top);
+        assert!(!persisted.ui.roster);
+    }
+}
@@ -0,0 +1,152 @@
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use chrono::Local;
+use tokio::{runtime::Runtime, time};
+
+use crate::{
+    audio::AudioPlayer,
+    channel::{message_channel, MessageReceiver},
+    config::Config,
+    discord::{run_client, save_open_sessions, DesktopAlerts, DiscordEventHandler},
+    enums::{ChannelMessage, ConnectionState},
+    gateway::{LocalGateway, Replay},
+    rules::RuleSet,
+    token::require_token,
+};
+
+/// Time left to print the last messages once the client stopped.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Runs the watcher without a window until SIGTERM or Ctrl+C.
+///
+/// Notifications, sounds and the event history work as in the UI, events
+/// are also printed to stdout so they end up in the journal.
+pub fn start_daemon(config: Config) {
+    // * Load discord token
+    let token = require_token(&config.token);
+
+    let rt = Runtime::new().expect("Unable to create Runtime");
+
+    rt.block_on(async {
+        let (tx, rx) = message_channel(None);
+        let watch_filter = Arc::new(RwLock::new(config.filter));
+        let rules = Arc::new(RwLock::new(RuleSet::load()));
+
+        let notifications = Arc::new(RwLock::new(config.notifications));
+
+        let audio = AudioPlayer::spawn(config.sounds, Some(tx.clone()));
+        let event_handler = DiscordEventHandler::new(tx, watch_filter, rules, audio, notifications);
+        let sessions = event_handler.sessions.clone();
+        // * Print events
+        let printer = tokio::spawn(print_messages(rx));
+
+        // * Run until the client stops or a signal arrives
+        run_client(token, event_handler, config.overrides.record, async {
+            wait_for_shutdown().await;
+            println!("Shutting down");
+        })
+        .await;
+        save_open_sessions(&mut *sessions.lock().await);
+
+        // The client and its handler are dropped, print what they sent last.
+        // A shard task still holding the handler must not keep the daemon up.
+        if time::timeout(DRAIN_TIMEOUT, printer).await.is_err() {
+            eprintln!("Some messages were not printed before shutting down");
+        }
+    });
+}
+
+/// Replays a recording to stdout, with the sounds and notifications of the
+/// daemon but without writing to the event history.
+pub fn start_replay(config: Config, replay: Replay) {
+    let rt = Runtime::new().expect("Unable to create Runtime");
+
+    rt.block_on(async {
+        let (tx, rx) = message_channel(None);
+        let watch_filter = Arc::new(RwLock::new(config.filter));
+        let rules = Arc::new(RwLock::new(RuleSet::load()));
+
+        let alerts = DesktopAlerts::new(
+            AudioPlayer::spawn(config.sounds, Some(tx.clone())),
+            Arc::new(RwLock::new(config.notifications)),
+            tx.clone(),
+        );
+        let gateway = LocalGateway::new(DiscordEventHandler::with_sinks(
+            tx,
+            watch_filter,
+            rules,
+            Arc::new(alerts),
+            None,
+        ));
+        // * Print events
+        let printer = tokio::spawn(print_messages(rx));
+
+        let result = gateway.replay(&replay).await;
+        // Closes the channel so the printer stops
+        drop(gateway);
+        let _ = printer.await;
+        match result {
+            Ok(count) => println!("Replayed {} payloads", count),
+            Err(e) => eprintln!("{}", e),
+        }
+    });
+}
+
+/// Prints the messages of the handler until it is gone.
+async fn print_messages(mut rx: MessageReceiver) {
+    while let Some(message) = rx.recv().await {
+        let dropped = rx.take_dropped();
+        if dropped > 0 {
+            eprintln!("{} messages dropped, stdout is not keeping up", dropped);
+        }
+        log_message(message);
+    }
+}
+
+fn log_message(message: ChannelMessage) {
+    let now = Local::now().format("%Y-%m-%d %H:%M:%S");
+    match message {
+        ChannelMessage::BotConnected(ready) => {
+            println!("{} Bot connected ( {} )", now, ready.user.name);
+        }
+        ChannelMessage::GuildAvailable(_, name) => {
+            println!("{} Watching {}", now, name);
+        }
+        ChannelMessage::Voice(event) => {
+            println!("{} [{}] {}", now, event.guild_name, event.description());
+        }
+        ChannelMessage::Custom(message) => {
+            println!("{} {}", now, message);
+        }
+        ChannelMessage::Connection(state) => match state {
+            // Already told by `BotConnected`
+            ConnectionState::Connected => (),
+            _ => println!("{} {}", now, state.label()),
+        },
+        // Already written to stderr by the handler
+        ChannelMessage::Error(_) => (),
+        ChannelMessage::Latency(_) => (),
+        ChannelMessage::DebugData(_) => (),
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_shutdown() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate()).expect("Unable to listen for SIGTERM");
+    tokio::select! {
+        _ = terminate.recv() => (),
+        _ = tokio::signal::ctrl_c() => (),
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_shutdown() {
+    tokio::signal::ctrl_c()
+        .await
+        .expect("Unable to listen for Ctrl+C");
+}
@@ -1,378 +1,1449 @@
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    error, fmt,
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
+};
 
-use egui_struct::EguiStruct;
-use lazy_static::lazy_static;
+use chrono::{DateTime, Local, Utc};
 use serenity::{
     async_trait,
-    cache::Cache,
-    http::Http,
+    client::bridge::gateway::event::ShardStageUpdateEvent,
+    gateway::{ConnectionStage, GatewayError},
     model::{
-        prelude::{ChannelType, Ready},
+        guild::{
+            audit_log::{Change, MemberAction},
+            Guild,
+        },
+        id::{ChannelId, GuildId, RoleId, UserId},
+        prelude::Ready,
         user::OnlineStatus,
         voice::VoiceState,
     },
-    prelude::{Context, EventHandler},
+    prelude::{Context, EventHandler, GatewayIntents},
+    Client,
+};
+use tokio::{
+    sync::{
+        oneshot::{self, error::TryRecvError},
+        Mutex,
+    },
+    task::JoinHandle,
+    time,
 };
-use tokio::sync::{mpsc::Sender, Mutex};
 
 use crate::{
-    enums::ChannelMessage,
-    utils::{play_sound, push_notification},
+    audio::AudioPlayer,
+    channel::MessageSender,
+    config::NotificationSettings,
+    enums::{ChannelMessage, ConnectionState, SoundKind, VoiceEventKind},
+    gateway::{GatewayPayload, Recorder},
+    models::{Roster, VoiceEvent, VoiceFlags, VoiceSession, WatchFilter},
+    rules::{evaluate, RuleSet},
+    sessions::SessionTracker,
+    storage::EventStore,
+    utils::{plural, push_notification},
 };
 
-lazy_static! {
-    static ref OLD_STATE: Arc<Mutex<Option<VoiceState>>> = Arc::new(Mutex::new(None));
+/// How often the heartbeat latency is read from the shard.
+const LATENCY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Failures of the Discord handler. They are logged and shown in the event
+/// list, the handler then carries on with the next guild or event.
+#[derive(Debug)]
+pub enum DiscordError {
+    /// A request to the Discord API failed.
+    Http {
+        action: &'static str,
+        source: serenity::Error,
+    },
+    /// A voice state points to a channel that is not a guild channel.
+    NotGuildChannel(ChannelId),
+    /// A voice change was reported without the channel it happened in.
+    MissingChannel(UserId),
+    /// Discord refused the bot token.
+    InvalidToken,
+    /// A recording could not be opened or read.
+    Recording {
+        action: &'static str,
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// The UI stopped receiving messages.
+    ChannelClosed,
+}
+
+impl DiscordError {
+    pub fn http(action: &'static str) -> impl FnOnce(serenity::Error) -> Self {
+        move |source| DiscordError::Http { action, source }
+    }
+
+    pub fn recording(action: &'static str, path: &Path) -> impl FnOnce(io::Error) -> Self {
+        let path = path.to_owned();
+        move |source| DiscordError::Recording {
+            action,
+            path,
+            source,
+        }
+    }
+}
+
+impl fmt::Display for DiscordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiscordError::Http { action, source } => {
+                write!(f, "Error when {}: {}", action, source)
+            }
+            DiscordError::NotGuildChannel(channel_id) => {
+                write!(f, "Channel {} is not a guild channel", channel_id)
+            }
+            DiscordError::MissingChannel(user_id) => {
+                write!(f, "Voice change of user {} has no channel", user_id)
+            }
+            DiscordError::InvalidToken => {
+                write!(
+                    f,
+                    "Discord refused the bot token, replace the token saved from the app, \
+                     DISCORD_TOKEN or the --token-file"
+                )
+            }
+            DiscordError::Recording {
+                action,
+                path,
+                source,
+            } => write!(
+                f,
+                "Error when {} recording {}: {}",
+                action,
+                path.display(),
+                source
+            ),
+            DiscordError::ChannelClosed => write!(f, "The UI is not receiving events"),
+        }
+    }
+}
+
+impl error::Error for DiscordError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            DiscordError::Http { source, .. } => Some(source),
+            DiscordError::Recording { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Last known voice state of every member currently in a voice channel,
+/// keyed by guild and user so each update is compared with that user's own
+/// previous state.
+#[derive(Debug, Default)]
+pub struct VoiceStateStore {
+    states: HashMap<(GuildId, UserId), VoiceState>,
+    /// Guilds whose voice states were received at least once.
+    synced: HashSet<GuildId>,
+}
+
+impl VoiceStateStore {
+    pub fn get(&self, guild_id: GuildId, user_id: UserId) -> Option<&VoiceState> {
+        self.states.get(&(guild_id, user_id))
+    }
+
+    pub fn is_synced(&self, guild_id: GuildId) -> bool {
+        self.synced.contains(&guild_id)
+    }
+
+    /// Replaces every known state of `guild_id` with `states`.
+    pub fn seed_guild(&mut self, guild_id: GuildId, states: impl IntoIterator<Item = VoiceState>) {
+        self.states.retain(|(guild, _), _| *guild != guild_id);
+        self.synced.insert(guild_id);
+        for state in states {
+            self.update(guild_id, state);
+        }
+    }
+
+    /// Like [`seed_guild`](Self::seed_guild), returning the previous and
+    /// current state of every member whose channel changed in between.
+    /// Members who left are given their previous state without a channel.
+    pub fn resync_guild(
+        &mut self,
+        guild_id: GuildId,
+        states: impl IntoIterator<Item = VoiceState>,
+    ) -> Vec<(Option<VoiceState>, VoiceState)> {
+        let states: Vec<VoiceState> = states
+            .into_iter()
+            .filter(|state| state.channel_id.is_some())
+            .collect();
+
+        let mut changes = vec![];
+        for state in states.iter() {
+            let old = self.get(guild_id, state.user_id);
+            if old.and_then(|old| old.channel_id) != state.channel_id {
+                changes.push((old.cloned(), state.clone()));
+            }
+        }
+        for ((guild, user_id), old) in self.states.iter() {
+            if *guild == guild_id && !states.iter().any(|state| state.user_id == *user_id) {
+                let mut left = old.clone();
+                left.channel_id = None;
+                changes.push((Some(old.clone()), left));
+            }
+        }
+
+        self.seed_guild(guild_id, states);
+        changes
+    }
+
+    /// Records `state` as the latest state of its user, forgetting users who
+    /// left voice. Returns the previous state if there was one.
+    pub fn update(&mut self, guild_id: GuildId, state: VoiceState) -> Option<VoiceState> {
+        let key = (guild_id, state.user_id);
+        if state.channel_id.is_some() {
+            self.states.insert(key, state)
+        } else {
+            self.states.remove(&key)
+        }
+    }
 }
 
-#[derive(Debug, Clone, EguiStruct)]
+/// The fields of a voice state the debug inspector compares.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct CustomVoiceState {
     pub guild_name: String,
     pub channel_name: String,
     pub self_deaf: bool,
     pub self_mute: bool,
+    pub deaf: bool,
+    pub mute: bool,
+    pub suppress: bool,
     pub self_stream: bool,
     pub self_video: bool,
     pub username: String,
 }
 
 impl CustomVoiceState {
-    async fn new(state: Option<VoiceState>, cache: &Arc<Cache>, http: &Arc<Http>) -> Self {
-        if state.is_none() {
-            return CustomVoiceState {
-                guild_name: String::default(),
-                channel_name: String::default(),
-                self_deaf: false,
-                self_mute: false,
-                self_stream: false,
-                self_video: false,
-                username: String::default(),
-            };
-        }
-
-        let state = state.unwrap();
+    async fn new(state: Option<VoiceState>, api: &dyn DiscordApi) -> Self {
+        let state = match state {
+            Some(state) => state,
+            None => return CustomVoiceState::default(),
+        };
 
+        // Debug data only, a missing channel name is not worth an error
         let channel_name = match state.channel_id {
-            Some(channel_id) => match channel_id.to_channel_cached(cache) {
-                Some(channel) => Some(channel.guild().unwrap()),
-                None => Some(
-                    state
-                        .channel_id
-                        .unwrap()
-                        .to_channel(http)
-                        .await
-                        .unwrap()
-                        .guild()
-                        .unwrap(),
-                ),
-            },
-            None => None,
-        };
-        let channel_name = match channel_name {
-            Some(channel) => channel.name,
+            Some(channel_id) => api.channel_name(channel_id).await.unwrap_or_default(),
             None => String::default(),
         };
 
         let guild_name = match state.guild_id {
-            Some(guild_id) => match guild_id.name(cache) {
-                Some(guild) => guild,
-                None => match guild_id.to_guild_cached(cache) {
-                    Some(guild) => guild.name,
-                    None => String::default(),
-                },
-            },
+            Some(guild_id) => api.guild_name(guild_id).await,
             None => String::default(),
         };
 
-        let username = match state.user_id.to_user_cached(cache).await {
-            Some(user) => user.name,
-            None => match state.user_id.to_user(http).await {
-                Ok(user) => user.name,
-                Err(_) => String::default(),
-            },
-        };
+        let username = api.user_name(state.user_id).await;
 
         CustomVoiceState {
             guild_name,
             channel_name,
             self_deaf: state.self_deaf,
             self_mute: state.self_mute,
+            deaf: state.deaf,
+            mute: state.mute,
+            suppress: state.suppress,
             self_stream: state.self_stream.unwrap_or(false),
             self_video: state.self_video,
             username,
         }
     }
+
+    /// The compared fields as `(name, value)`, the user and the guild are
+    /// the same on both sides of an update.
+    pub fn fields(&self) -> [(&'static str, String); 8] {
+        [
+            ("channel", self.channel_name.clone()),
+            ("self_mute", self.self_mute.to_string()),
+            ("self_deaf", self.self_deaf.to_string()),
+            ("mute", self.mute.to_string()),
+            ("deaf", self.deaf.to_string()),
+            ("suppress", self.suppress.to_string()),
+            ("self_stream", self.self_stream.to_string()),
+            ("self_video", self.self_video.to_string()),
+        ]
+    }
 }
 
-pub struct DiscordEventHandler {
-    pub tx: Sender<ChannelMessage>,
-    pub ctx: egui::Context,
+/// A voice state update as the debug inspector shows it.
+#[derive(Debug, Clone)]
+pub struct DebugUpdate {
+    pub timestamp: DateTime<Utc>,
+    pub old_state: CustomVoiceState,
+    pub new_state: CustomVoiceState,
+    /// The new voice state as serenity parsed it, written back to pretty
+    /// printed JSON. Fields serenity doesn't know about are missing.
+    pub payload: String,
 }
 
+impl DebugUpdate {
+    /// Names of the fields that differ between the two states.
+    pub fn changed_fields(&self) -> Vec<&'static str> {
+        self.old_state
+            .fields()
+            .into_iter()
+            .zip(self.new_state.fields())
+            .filter(|(old, new)| old.1 != new.1)
+            .map(|(old, _)| old.0)
+            .collect()
+    }
+
+    /// Whether the user, guild or channels contain `query`, ignoring case.
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        [
+            &self.new_state.username,
+            &self.new_state.guild_name,
+            &self.old_state.channel_name,
+            &self.new_state.channel_name,
+        ]
+        .iter()
+        .any(|text| text.to_lowercase().contains(&query))
+    }
+
+    /// Plain text version of the update, to paste in a bug report.
+    pub fn report(&self) -> String {
+        let mut report = format!(
+            "{} {} in {}\n",
+            self.timestamp.to_rfc3339(),
+            self.new_state.username,
+            self.new_state.guild_name
+        );
+        for ((name, old), (_, new)) in self
+            .old_state
+            .fields()
+            .into_iter()
+            .zip(self.new_state.fields())
+        {
+            let mark = if old == new { ' ' } else { '*' };
+            report.push_str(&format!("{} {}: {} -> {}\n", mark, name, old, new));
+        }
+        report.push_str(&self.payload);
+        report
+    }
+}
+
+/// Lookups the handler makes besides the gateway events. A [`Context`]
+/// answers them from the cache and the HTTP API, a
+/// [`LocalApi`](crate::gateway::LocalApi) from the payloads it was fed.
 #[async_trait]
-impl EventHandler for DiscordEventHandler {
-    async fn ready(&self, ctx: Context, ready: Ready) {
-        ctx.set_presence(None, OnlineStatus::Invisible).await;
-        self.tx
-            .send(ChannelMessage::BotConnected(Box::new(ready.clone())))
-            .await
-            .unwrap();
-        self.ctx.request_repaint();
+pub trait DiscordApi: Send + Sync {
+    /// Name of `guild_id`, empty when it can't be found.
+    async fn guild_name(&self, guild_id: GuildId) -> String;
+
+    async fn channel_name(&self, channel_id: ChannelId) -> Result<String, DiscordError>;
+
+    /// Name of `user_id`, empty when it can't be found.
+    async fn user_name(&self, user_id: UserId) -> String;
+
+    /// Roles of `user_id` in `guild_id`, for voice states without the member.
+    async fn member_roles(&self, guild_id: GuildId, user_id: UserId) -> Vec<RoleId>;
+
+    /// Looks up in the audit log who server muted or deafened `user_id`.
+    ///
+    /// Returns `None` when the bot lacks the `View Audit Log` permission or no
+    /// matching entry was written in the last minute.
+    async fn moderator_name(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        kind: VoiceEventKind,
+    ) -> Option<String>;
+}
+
+#[async_trait]
+impl DiscordApi for Context {
+    async fn guild_name(&self, guild_id: GuildId) -> String {
+        match guild_id.name(&self.cache) {
+            Some(name) => name,
+            None => match guild_id.to_partial_guild(&self.http).await {
+                Ok(guild) => guild.name,
+                Err(_) => String::default(),
+            },
+        }
+    }
 
-        let guild = ready.guilds[0]
-            .id
-            .to_partial_guild(&ctx.http)
+    async fn channel_name(&self, channel_id: ChannelId) -> Result<String, DiscordError> {
+        let channel = match channel_id.to_channel_cached(&self.cache) {
+            Some(channel) => channel,
+            None => channel_id
+                .to_channel(&self.http)
+                .await
+                .map_err(DiscordError::http("fetching channel"))?,
+        };
+
+        match channel.guild() {
+            Some(channel) => Ok(channel.name),
+            None => Err(DiscordError::NotGuildChannel(channel_id)),
+        }
+    }
+
+    async fn user_name(&self, user_id: UserId) -> String {
+        match user_id.to_user_cached(&self.cache).await {
+            Some(user) => user.name,
+            None => match user_id.to_user(&self.http).await {
+                Ok(user) => user.name,
+                Err(_) => String::default(),
+            },
+        }
+    }
+
+    async fn member_roles(&self, guild_id: GuildId, user_id: UserId) -> Vec<RoleId> {
+        match self.cache.member(guild_id, user_id) {
+            Some(member) => member.roles,
+            None => vec![],
+        }
+    }
+
+    async fn moderator_name(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        kind: VoiceEventKind,
+    ) -> Option<String> {
+        let logs = guild_id
+            .audit_logs(
+                &self.http,
+                Some(MemberAction::Update as u8),
+                None,
+                None,
+                Some(10),
+            )
             .await
-            .unwrap();
-
-        let channels = guild.channels(&ctx.http).await.unwrap();
-        for (_, channel) in channels {
-            if channel.kind == ChannelType::Voice {
-                let joined_members = match channel.members(&ctx.cache).await {
-                    Ok(members) => members,
-                    Err(_) => {
-                        let guild = match channel.guild(&ctx.cache) {
-                            Some(guild) => guild,
-                            None => continue,
-                        };
-                        guild
-                            .voice_states
-                            .values()
-                            .filter_map(|v| {
-                                v.channel_id.and_then(|c| {
-                                    if c == channel.id {
-                                        guild.members.get(&v.user_id).cloned()
-                                    } else {
-                                        None
-                                    }
-                                })
-                            })
-                            .collect()
+            .ok()?;
+
+        let now = Utc::now().timestamp();
+        let entry = logs.entries.iter().find(|entry| {
+            let is_recent = now - entry.id.created_at().unix_timestamp() <= 60;
+            let is_match = entry.changes.iter().flatten().any(|change| {
+                matches!(
+                    (kind, change),
+                    (
+                        VoiceEventKind::ServerMuted | VoiceEventKind::ServerUnmuted,
+                        Change::Mute { .. }
+                    ) | (
+                        VoiceEventKind::ServerDeafened | VoiceEventKind::ServerUndeafened,
+                        Change::Deaf { .. }
+                    )
+                )
+            });
+            entry.target_id == Some(user_id.0) && is_recent && is_match
+        })?;
+
+        match logs.users.get(&entry.user_id) {
+            Some(user) => Some(user.name.clone()),
+            None => Some(self.user_name(entry.user_id).await),
+        }
+    }
+}
+
+/// Lists every change between a member's previous and current voice state.
+///
+/// Channel changes come first, followed by the flags that changed while the
+/// member stayed in voice. Flags are not reported on join or leave.
+pub fn diff_voice_states(old: Option<&VoiceState>, new: &VoiceState) -> Vec<VoiceEventKind> {
+    let old_channel_id = old.and_then(|old| old.channel_id);
+
+    let mut changes = vec![];
+    match (old_channel_id, new.channel_id) {
+        (None, Some(_)) => changes.push(VoiceEventKind::Joined),
+        (Some(_), None) => changes.push(VoiceEventKind::Left),
+        (Some(old), Some(new)) if old != new => changes.push(VoiceEventKind::Moved),
+        _ => (),
+    }
+
+    let old = match old {
+        Some(old) if old.channel_id.is_some() && new.channel_id.is_some() => old,
+        _ => return changes,
+    };
+    let before = VoiceFlags::from(old);
+    let after = VoiceFlags::from(new);
+
+    let toggles = [
+        (
+            before.self_mute,
+            after.self_mute,
+            VoiceEventKind::Muted,
+            VoiceEventKind::Unmuted,
+        ),
+        (
+            before.self_deaf,
+            after.self_deaf,
+            VoiceEventKind::Deafened,
+            VoiceEventKind::Undeafened,
+        ),
+        (
+            before.self_stream,
+            after.self_stream,
+            VoiceEventKind::StartedStreaming,
+            VoiceEventKind::StoppedStreaming,
+        ),
+        (
+            before.self_video,
+            after.self_video,
+            VoiceEventKind::CameraOn,
+            VoiceEventKind::CameraOff,
+        ),
+        (
+            before.mute,
+            after.mute,
+            VoiceEventKind::ServerMuted,
+            VoiceEventKind::ServerUnmuted,
+        ),
+        (
+            before.deaf,
+            after.deaf,
+            VoiceEventKind::ServerDeafened,
+            VoiceEventKind::ServerUndeafened,
+        ),
+        (
+            before.suppress,
+            after.suppress,
+            VoiceEventKind::Suppressed,
+            VoiceEventKind::Unsuppressed,
+        ),
+    ];
+    for (before, after, on, off) in toggles {
+        if before != after {
+            changes.push(if after { on } else { off });
+        }
+    }
+
+    changes
+}
+
+/// Gateway intents requested by the client. `GUILDS` is needed for the
+/// `GUILD_CREATE` events seeding the voice states of every guild.
+pub fn intents() -> GatewayIntents {
+    GatewayIntents::GUILDS | GatewayIntents::GUILD_VOICE_STATES | GatewayIntents::GUILD_PRESENCES
+    // | GatewayIntents::GUILD_MESSAGES
+    // | GatewayIntents::DIRECT_MESSAGES
+    // | GatewayIntents::MESSAGE_CONTENT
+}
+
+/// Creates the Discord client listening to voice state changes with `handler`,
+/// writing the payloads to `recorder` if any.
+pub async fn create_client(
+    token: &str,
+    handler: DiscordEventHandler,
+    recorder: Option<Recorder>,
+) -> serenity::Result<Client> {
+    let builder = Client::builder(token, intents()).event_handler(handler);
+    match recorder {
+        Some(recorder) => builder.raw_event_handler(recorder).await,
+        None => builder.await,
+    }
+}
+
+/// Runs a client until it stops or `stop` completes, sending the connection
+/// state and the heartbeat latency to the UI. The payloads are recorded to
+/// `record` if any.
+pub async fn run_client(
+    token: String,
+    handler: DiscordEventHandler,
+    record: Option<PathBuf>,
+    stop: impl Future<Output = ()>,
+) {
+    let tx = handler.tx.clone();
+    send_connection(&tx, ConnectionState::Connecting);
+
+    let recorder = match record {
+        Some(path) => match Recorder::open(&path, tx.clone()) {
+            Ok(recorder) => Some(recorder),
+            Err(e) => {
+                report(&tx, DiscordError::recording("opening", &path)(e));
+                None
+            }
+        },
+        None => None,
+    };
+    let mut client = match create_client(&token, handler, recorder).await {
+        Ok(client) => client,
+        Err(e) => {
+            report(&tx, DiscordError::http("creating client")(e));
+            send_connection(&tx, ConnectionState::Disconnected);
+            return;
+        }
+    };
+
+    let shard_manager = client.shard_manager.clone();
+    let run = client.start_shards(1);
+    tokio::pin!(run);
+    tokio::pin!(stop);
+    let mut latency_timer = time::interval(LATENCY_INTERVAL);
+    let state = loop {
+        tokio::select! {
+            result = &mut run => {
+                break match result {
+                    Ok(()) => ConnectionState::Disconnected,
+                    Err(serenity::Error::Gateway(GatewayError::InvalidAuthentication)) => {
+                        report(&tx, DiscordError::InvalidToken);
+                        ConnectionState::AuthFailed
+                    }
+                    Err(e) => {
+                        report(&tx, DiscordError::http("running client")(e));
+                        ConnectionState::Disconnected
                     }
                 };
+            }
+            _ = &mut stop => {
+                shard_manager.lock().await.shutdown_all().await;
+                break ConnectionState::Disconnected;
+            }
+            _ = latency_timer.tick() => {
+                let manager = shard_manager.lock().await;
+                let latency = manager
+                    .runners
+                    .lock()
+                    .await
+                    .values()
+                    .find_map(|runner| runner.latency);
+                if let Some(latency) = latency {
+                    let _ = tx.send(ChannelMessage::Latency(latency));
+                }
+            }
+        }
+    };
+    send_connection(&tx, state);
+}
+
+/// Shows `state` in the status bar. The client goes on when the UI is gone,
+/// until it is stopped.
+pub fn send_connection(tx: &MessageSender, state: ConnectionState) {
+    let _ = tx.send(ChannelMessage::Connection(state));
+}
+
+/// Logs `error` and shows it in the event list.
+fn report(tx: &MessageSender, error: DiscordError) {
+    match error {
+        // Nothing is left to show the error when the UI is gone
+        DiscordError::ChannelClosed => tx.log_error(error.to_string()),
+        _ => tx.show_error(error.to_string()),
+    }
+}
+
+/// A Discord client running on the tokio runtime, which the UI can stop and
+/// start again, with the same or another token.
+pub struct DiscordClient {
+    make_handler: Box<dyn Fn() -> DiscordEventHandler>,
+    /// Recording the payloads are written to, see [`Recorder`].
+    record: Option<PathBuf>,
+    token: Option<String>,
+    voice_states: Arc<Mutex<VoiceStateStore>>,
+    sessions: Arc<Mutex<SessionTracker>>,
+    /// Given to every handler, cleared when connecting with a new token.
+    pub roster: Arc<RwLock<Roster>>,
+    stop_tx: Option<oneshot::Sender<()>>,
+    /// Task of the last client, which the next one waits for so that the
+    /// two never handle the same voice changes or report their state out of
+    /// order.
+    task: Option<JoinHandle<()>>,
+}
+
+impl DiscordClient {
+    pub fn new(
+        record: Option<PathBuf>,
+        make_handler: impl Fn() -> DiscordEventHandler + 'static,
+    ) -> Self {
+        DiscordClient {
+            make_handler: Box::new(make_handler),
+            record,
+            token: None,
+            voice_states: Arc::default(),
+            sessions: Arc::default(),
+            roster: Arc::default(),
+            stop_tx: None,
+            task: None,
+        }
+    }
+
+    /// Connects with `token`, stopping the running client first. The voice
+    /// states are reported again from scratch, the sessions open until now
+    /// are saved.
+    pub fn start(&mut self, token: String) {
+        self.stop();
+        self.save_open_sessions();
+        self.voice_states = Arc::default();
+        self.sessions = Arc::default();
+        *self.roster.write().unwrap() = Roster::default();
+        self.connect(token);
+    }
+
+    fn connect(&mut self, token: String) {
+        self.stop();
+
+        let mut handler = (self.make_handler)();
+        handler.voice_states = self.voice_states.clone();
+        handler.sessions = self.sessions.clone();
+        handler.roster = self.roster.clone();
+        let (stop_tx, mut stop_rx) = oneshot::channel::<()>();
+        self.token = Some(token.clone());
+        self.stop_tx = Some(stop_tx);
+        let record = self.record.clone();
+        let previous = self.task.take();
+        self.task = Some(tokio::spawn(async move {
+            if let Some(previous) = previous {
+                let _ = previous.await;
+            }
+            // Stopped again while the previous client was shutting down
+            if !matches!(stop_rx.try_recv(), Err(TryRecvError::Empty)) {
+                return;
+            }
+            run_client(token, handler, record, async {
+                let _ = stop_rx.await;
+            })
+            .await;
+        }));
+    }
+
+    /// Connects again with the last token, reporting only the voice changes
+    /// missed in between. Returns `false` when there is no token to connect
+    /// with.
+    pub fn reconnect(&mut self) -> bool {
+        match self.token.clone() {
+            Some(token) => {
+                self.connect(token);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Disconnects and forgets the token.
+    pub fn stop(&mut self) {
+        self.token = None;
+        if let Some(stop_tx) = self.stop_tx.take() {
+            // The client is gone already if it failed to start
+            let _ = stop_tx.send(());
+        }
+    }
+
+    /// Sessions still open, as if they ended at `now`.
+    pub fn open_sessions(&self, now: DateTime<Utc>) -> Vec<VoiceSession> {
+        self.sessions.blocking_lock().open_sessions(now)
+    }
+
+    /// Ends the open sessions now and saves them to the history, before the
+    /// watcher exits or forgets them.
+    pub fn save_open_sessions(&self) {
+        save_open_sessions(&mut self.sessions.blocking_lock());
+    }
+}
+
+/// Ends the sessions of `tracker` still open now and saves them to the
+/// history.
+pub fn save_open_sessions(tracker: &mut SessionTracker) {
+    let sessions = tracker.close_all(Utc::now());
+    if sessions.is_empty() {
+        return;
+    }
+    let store = match EventStore::open() {
+        Ok(store) => store,
+        Err(e) => {
+            eprintln!("Error when opening event history: {}", e);
+            return;
+        }
+    };
+    for session in sessions {
+        if let Err(e) = store.insert_session(&session) {
+            eprintln!("Error when saving session: {}", e);
+        }
+    }
+}
+
+/// Voice state sync following a `ready` event, summed up in one toast once
+/// every guild of the event arrived.
+#[derive(Debug, Default)]
+struct SyncProgress {
+    pending: HashSet<GuildId>,
+    guilds: usize,
+    /// The voice states were known before, the bot reconnected.
+    is_reconnect: bool,
+    members: usize,
+    changes: usize,
+}
+
+/// Where the handler sends its toasts and sounds.
+pub trait AlertSink: Send + Sync {
+    fn toast(&self, body: &str);
+    fn sound(&self, kind: SoundKind);
+}
+
+/// Desktop notifications and the audio player.
+pub struct DesktopAlerts {
+    pub audio: AudioPlayer,
+    pub notifications: Arc<RwLock<NotificationSettings>>,
+    /// Where a failed notification is logged.
+    errors: MessageSender,
+    /// Set once a notification failed, the next failures are not logged.
+    failed: AtomicBool,
+}
 
-                for member in joined_members {
-                    push_notification(&format!(
-                        "{} is already in {}",
-                        member.user.name.clone(),
-                        channel.name.clone()
-                    ));
-                    play_sound();
-
-                    self.tx
-                        .send(ChannelMessage::UserAlreadyInChannel(
-                            member.user.name.clone(),
-                            channel.name.clone(),
-                        ))
-                        .await
-                        .unwrap();
-                    self.ctx.request_repaint();
+impl DesktopAlerts {
+    pub fn new(
+        audio: AudioPlayer,
+        notifications: Arc<RwLock<NotificationSettings>>,
+        errors: MessageSender,
+    ) -> Self {
+        DesktopAlerts {
+            audio,
+            notifications,
+            errors,
+            failed: AtomicBool::new(false),
+        }
+    }
+}
+
+impl AlertSink for DesktopAlerts {
+    fn toast(&self, body: &str) {
+        // Headless machines and SSH sessions have no notification server,
+        // which would fail every toast
+        if let Err(e) = push_notification(body, *self.notifications.read().unwrap()) {
+            if !self.failed.swap(true, Ordering::Relaxed) {
+                self.errors.log_error(format!(
+                    "Error when showing notification: {}, the next failures are not logged",
+                    e
+                ));
+            }
+        }
+    }
+
+    fn sound(&self, kind: SoundKind) {
+        self.audio.play(kind);
+    }
+}
+
+pub struct DiscordEventHandler {
+    pub tx: MessageSender,
+    pub watch_filter: Arc<RwLock<WatchFilter>>,
+    pub rules: Arc<RwLock<RuleSet>>,
+    pub alerts: Arc<dyn AlertSink>,
+    /// Who is in voice, updated with every event whatever the UI is sent.
+    pub roster: Arc<RwLock<Roster>>,
+    /// Shared by the handlers of a [`DiscordClient`], so a reconnect is
+    /// compared with the states seen before it.
+    voice_states: Arc<Mutex<VoiceStateStore>>,
+    /// Shared like `voice_states`, so sessions go on across reconnects.
+    pub sessions: Arc<Mutex<SessionTracker>>,
+    sync: Mutex<Option<SyncProgress>>,
+    store: Option<Mutex<EventStore>>,
+}
+
+impl DiscordEventHandler {
+    pub fn new(
+        tx: MessageSender,
+        watch_filter: Arc<RwLock<WatchFilter>>,
+        rules: Arc<RwLock<RuleSet>>,
+        audio: AudioPlayer,
+        notifications: Arc<RwLock<NotificationSettings>>,
+    ) -> Self {
+        let store = match EventStore::open() {
+            Ok(store) => Some(store),
+            Err(e) => {
+                tx.log_error(format!("Error when opening event history: {}", e));
+                None
+            }
+        };
+        let alerts = DesktopAlerts::new(audio, notifications, tx.clone());
+        Self::with_sinks(tx, watch_filter, rules, Arc::new(alerts), store)
+    }
+
+    /// Creates a handler sending its toasts and sounds to `alerts` and
+    /// saving events to `store`, if any.
+    pub fn with_sinks(
+        tx: MessageSender,
+        watch_filter: Arc<RwLock<WatchFilter>>,
+        rules: Arc<RwLock<RuleSet>>,
+        alerts: Arc<dyn AlertSink>,
+        store: Option<EventStore>,
+    ) -> Self {
+        DiscordEventHandler {
+            tx,
+            watch_filter,
+            rules,
+            alerts,
+            roster: Arc::default(),
+            voice_states: Arc::default(),
+            sessions: Arc::default(),
+            sync: Mutex::new(None),
+            store: store.map(Mutex::new),
+        }
+    }
+
+    fn is_guild_watched(&self, guild_id: GuildId) -> bool {
+        self.watch_filter.read().unwrap().is_guild_enabled(guild_id)
+    }
+
+    /// Sends `message` to the UI.
+    fn send(&self, message: ChannelMessage) -> Result<(), DiscordError> {
+        self.tx
+            .send(message)
+            .map_err(|_| DiscordError::ChannelClosed)
+    }
+
+    /// Logs `error` and shows it in the event list.
+    pub fn report(&self, error: DiscordError) {
+        report(&self.tx, error);
+    }
+
+    /// Name of `channel_id`, reporting a failed lookup and using the ID
+    /// instead so the event is still shown.
+    async fn channel_name_or_id(&self, channel_id: ChannelId, api: &dyn DiscordApi) -> String {
+        match api.channel_name(channel_id).await {
+            Ok(name) => name,
+            Err(e) => {
+                self.report(e);
+                channel_id.to_string()
+            }
+        }
+    }
+
+    /// Saves `event` to the history, applies the notification rules to it and
+    /// forwards it to the UI when its category is watched. Toasts and sounds
+    /// are left out unless `alert`.
+    async fn dispatch(&self, event: VoiceEvent, alert: bool) -> Result<(), DiscordError> {
+        // Sessions and the roster count every change, whatever the UI shows
+        self.roster.write().unwrap().apply(&event);
+        let session = self.sessions.lock().await.apply(&event);
+        if let (Some(session), Some(ref store)) = (session, &self.store) {
+            if let Err(e) = store.lock().await.insert_session(&session) {
+                self.tx
+                    .log_error(format!("Error when saving session: {}", e));
+            }
+        }
+
+        // The history keeps every category, exports filter it afterwards
+        if let Some(ref store) = self.store {
+            if let Err(e) = store.lock().await.insert(&event) {
+                self.tx.log_error(format!("Error when saving event: {}", e));
+            }
+        }
+
+        if !self
+            .watch_filter
+            .read()
+            .unwrap()
+            .is_category_enabled(event.kind.category())
+        {
+            return Ok(());
+        }
+
+        let outcome = evaluate(
+            &self.rules.read().unwrap().rules,
+            &event,
+            Local::now().time(),
+        );
+        if alert && outcome.toast {
+            self.alerts.toast(&event.to_string());
+        }
+        if alert && outcome.sound {
+            self.alerts.sound(event.kind.sound());
+        }
+
+        if outcome.log {
+            self.send(ChannelMessage::Voice(event))?;
+        }
+        Ok(())
+    }
+
+    /// Name of a channel of `guild`, looked up like
+    /// [`channel_name_or_id`](Self::channel_name_or_id) when it is missing.
+    async fn guild_channel_name(
+        &self,
+        api: &dyn DiscordApi,
+        guild: &Guild,
+        channel_id: ChannelId,
+    ) -> String {
+        match guild
+            .channels
+            .get(&channel_id)
+            .and_then(|channel| channel.clone().guild())
+        {
+            Some(channel) => channel.name,
+            None => self.channel_name_or_id(channel_id, api).await,
+        }
+    }
+
+    /// Builds the event of a voice change found while syncing `guild`,
+    /// received at `time`.
+    async fn sync_event(
+        &self,
+        api: &dyn DiscordApi,
+        guild: &Guild,
+        kind: VoiceEventKind,
+        old_state: Option<&VoiceState>,
+        new_state: &VoiceState,
+        time: DateTime<Utc>,
+    ) -> Result<VoiceEvent, DiscordError> {
+        let old_channel_id = old_state.and_then(|old| old.channel_id);
+        let channel_id = match kind {
+            VoiceEventKind::Left => old_channel_id,
+            _ => new_state.channel_id,
+        }
+        .ok_or(DiscordError::MissingChannel(new_state.user_id))?;
+        let from_channel_id = match kind {
+            VoiceEventKind::Moved => old_channel_id,
+            _ => None,
+        };
+        let from_channel_name = match from_channel_id {
+            Some(channel_id) => Some(self.guild_channel_name(api, guild, channel_id).await),
+            None => None,
+        };
+
+        let member = guild
+            .members
+            .get(&new_state.user_id)
+            .or(new_state.member.as_ref());
+        let user_name = match member {
+            Some(member) => member.user.name.clone(),
+            None => api.user_name(new_state.user_id).await,
+        };
+        let after = VoiceFlags::from(new_state);
+
+        Ok(VoiceEvent {
+            timestamp: time,
+            kind,
+            guild_id: guild.id,
+            guild_name: guild.name.clone(),
+            user_id: new_state.user_id,
+            user_name,
+            roles: member
+                .map(|member| member.roles.clone())
+                .unwrap_or_default(),
+            channel_id,
+            channel_name: self.guild_channel_name(api, guild, channel_id).await,
+            from_channel_id,
+            from_channel_name,
+            before: old_state.map(VoiceFlags::from).unwrap_or(after),
+            after,
+            moderator: None,
+            resynced: false,
+        })
+    }
+
+    /// Reports who is in the voice channels of `guild` the first time it is
+    /// seen. After a reconnect, only the joins, leaves and moves that
+    /// happened while disconnected are reported.
+    ///
+    /// The events are not toasted one by one, a summary is toasted once every
+    /// guild of the `ready` event is synced.
+    async fn sync_guild(
+        &self,
+        api: &dyn DiscordApi,
+        guild: Guild,
+        time: DateTime<Utc>,
+    ) -> Result<(), DiscordError> {
+        self.send(ChannelMessage::GuildAvailable(guild.id, guild.name.clone()))?;
+
+        let states = guild.voice_states.values().cloned().collect::<Vec<_>>();
+        let (is_resync, changes) = {
+            let mut voice_states = self.voice_states.lock().await;
+            if voice_states.is_synced(guild.id) {
+                (true, voice_states.resync_guild(guild.id, states))
+            } else {
+                voice_states.seed_guild(guild.id, states.clone());
+                (
+                    false,
+                    states.into_iter().map(|state| (None, state)).collect(),
+                )
+            }
+        };
+
+        let mut count = 0;
+        if self.is_guild_watched(guild.id) {
+            for (old_state, new_state) in changes {
+                let kinds = if is_resync {
+                    diff_voice_states(old_state.as_ref(), &new_state)
+                        .into_iter()
+                        .filter(|kind| {
+                            matches!(
+                                kind,
+                                VoiceEventKind::Joined
+                                    | VoiceEventKind::Left
+                                    | VoiceEventKind::Moved
+                            )
+                        })
+                        .collect()
+                } else {
+                    vec![VoiceEventKind::AlreadyIn]
+                };
+
+                for kind in kinds {
+                    let mut event = self
+                        .sync_event(api, &guild, kind, old_state.as_ref(), &new_state, time)
+                        .await?;
+                    event.resynced = is_resync;
+                    self.dispatch(event, false).await?;
+                    count += 1;
                 }
             }
         }
+
+        self.finish_sync(guild.id, is_resync, count).await;
+        Ok(())
     }
 
-    async fn voice_state_update(
+    /// Counts the events of a synced guild, toasting the summary after the
+    /// last guild of the `ready` event.
+    async fn finish_sync(&self, guild_id: GuildId, is_resync: bool, count: usize) {
+        let mut sync = self.sync.lock().await;
+        let progress = match *sync {
+            Some(ref mut progress) if progress.pending.contains(&guild_id) => progress,
+            _ => return,
+        };
+        progress.pending.remove(&guild_id);
+        if is_resync {
+            progress.changes += count;
+        } else {
+            progress.members += count;
+        }
+        if !progress.pending.is_empty() {
+            return;
+        }
+
+        let summary = if !progress.is_reconnect {
+            Some(format!(
+                "Watching {}, {} in voice",
+                plural(progress.guilds, "guild"),
+                plural(progress.members, "member")
+            ))
+        } else if progress.changes > 0 {
+            Some(format!(
+                "Reconnected, {} while disconnected",
+                plural(progress.changes, "voice change")
+            ))
+        } else {
+            None
+        };
+        *sync = None;
+
+        if let Some(summary) = summary {
+            self.alerts.toast(&summary);
+        }
+    }
+
+    async fn handle_voice_state_update(
         &self,
-        ctx: Context,
+        api: &dyn DiscordApi,
         old_state: Option<VoiceState>,
         new_state: VoiceState,
-    ) {
+        time: DateTime<Utc>,
+    ) -> Result<(), DiscordError> {
+        let guild_id = match new_state.guild_id {
+            Some(guild_id) => guild_id,
+            None => return Ok(()),
+        };
+
+        let previous_state = self
+            .voice_states
+            .lock()
+            .await
+            .update(guild_id, new_state.clone());
         let old_state = match old_state {
             Some(state) => Some(state),
-            None => match OLD_STATE.lock().await.clone() {
-                Some(state) => Some(state),
-                None => None,
-            },
+            None => previous_state,
         };
 
-        self.tx
-            .send(ChannelMessage::DebugData(
-                CustomVoiceState::new(old_state.clone(), &ctx.cache, &ctx.http).await,
-                CustomVoiceState::new(Some(new_state.clone()), &ctx.cache, &ctx.http).await,
-            ))
-            .await
-            .unwrap();
-        self.ctx.request_repaint();
+        if !self.is_guild_watched(guild_id) {
+            return Ok(());
+        }
 
-        let old_user = match old_state.clone() {
-            Some(old) => match old.user_id.to_user_cached(&ctx.cache).await {
-                Some(user) => Some(user),
-                None => Some(old.user_id.to_user(&ctx.http).await.unwrap()),
-            },
-            None => None,
-        };
-        let new_user = match new_state.user_id.to_user_cached(&ctx.cache).await {
-            Some(user) => user,
-            None => new_state.user_id.to_user(&ctx.http).await.unwrap(),
+        let payload =
+            serde_json::to_string_pretty(&GatewayPayload::VoiceStateUpdate(new_state.clone()))
+                .unwrap_or_default();
+        self.send(ChannelMessage::DebugData(Box::new(DebugUpdate {
+            timestamp: time,
+            old_state: CustomVoiceState::new(old_state.clone(), api).await,
+            new_state: CustomVoiceState::new(Some(new_state.clone()), api).await,
+            payload,
+        })))?;
+
+        let changes = diff_voice_states(old_state.as_ref(), &new_state);
+        if changes.is_empty() {
+            if old_state.is_none() && new_state.channel_id.is_none() {
+                self.send(ChannelMessage::Custom(format!(
+                    "Unknown event:\n\told_state: {:?}\n\tnew_state: {:?}",
+                    old_state, new_state
+                )))?;
+            }
+            return Ok(());
+        }
+
+        let old_channel_id = old_state.as_ref().and_then(|old| old.channel_id);
+        let before = match old_state {
+            Some(ref old) => VoiceFlags::from(old),
+            None => VoiceFlags::default(),
         };
+        let after = VoiceFlags::from(&new_state);
 
-        let old_voice_channel = match old_state.clone() {
-            Some(old) => match old.channel_id {
-                Some(channel_id) => match channel_id.to_channel_cached(&ctx.cache) {
-                    Some(channel) => Some(channel.guild().unwrap()),
-                    None => Some(
-                        old.channel_id
-                            .unwrap()
-                            .to_channel(&ctx.http)
-                            .await
-                            .unwrap()
-                            .guild()
-                            .unwrap(),
-                    ),
-                },
-                None => None,
-            },
+        let guild_name = api.guild_name(guild_id).await;
+        let user_name = api.user_name(new_state.user_id).await;
+        let roles = match new_state.member {
+            Some(ref member) => member.roles.clone(),
+            None => api.member_roles(guild_id, new_state.user_id).await,
+        };
+        let old_channel_name = match old_channel_id {
+            Some(channel_id) => Some(self.channel_name_or_id(channel_id, api).await),
             None => None,
         };
-        let new_voice_channel = match new_state.channel_id {
-            Some(channel_id) => match channel_id.to_channel_cached(&ctx.cache) {
-                Some(channel) => Some(channel.guild().unwrap()),
-                None => Some(
-                    new_state
-                        .channel_id
-                        .unwrap()
-                        .to_channel(&ctx.http)
-                        .await
-                        .unwrap()
-                        .guild()
-                        .unwrap(),
-                ),
-            },
+        let new_channel_name = match new_state.channel_id {
+            Some(channel_id) => Some(self.channel_name_or_id(channel_id, api).await),
             None => None,
         };
 
-        // check if user joined a voice channel, muted, deafened, moved to another voice channel, or left a voice channel
-        if old_state.is_some() && new_state.channel_id.is_none() {
-            push_notification(&format!(
-                "{} left {}",
-                old_user.clone().unwrap().name.clone(),
-                old_voice_channel.clone().unwrap().name.clone()
-            ));
-            play_sound();
-
-            self.tx
-                .send(ChannelMessage::UserLeftChannel(
-                    old_user.clone().unwrap().name.clone(),
-                    old_voice_channel.clone().unwrap().name.clone(),
-                ))
-                .await
-                .unwrap();
-            self.ctx.request_repaint();
-        } else if old_state.is_some() && new_state.channel_id.is_some() {
-            if old_state.clone().unwrap().channel_id != new_state.channel_id {
-                push_notification(&format!(
-                    "{} moved from {} to {}",
-                    new_user.name.clone(),
-                    old_voice_channel.clone().unwrap().name.clone(),
-                    new_voice_channel.clone().unwrap().name.clone()
-                ));
-                play_sound();
+        for kind in changes {
+            let (channel_id, channel_name) = match kind {
+                VoiceEventKind::Left => (old_channel_id, old_channel_name.clone()),
+                _ => (new_state.channel_id, new_channel_name.clone()),
+            };
+            let (channel_id, channel_name) = match (channel_id, channel_name) {
+                (Some(channel_id), Some(channel_name)) => (channel_id, channel_name),
+                _ => return Err(DiscordError::MissingChannel(new_state.user_id)),
+            };
+            let (from_channel_id, from_channel_name) = match kind {
+                VoiceEventKind::Moved => (old_channel_id, old_channel_name.clone()),
+                _ => (None, None),
+            };
 
-                self.tx
-                    .send(ChannelMessage::UserMoved(
-                        new_user.name.clone(),
-                        old_voice_channel.clone().unwrap().name.clone(),
-                        new_voice_channel.clone().unwrap().name.clone(),
-                    ))
-                    .await
-                    .unwrap();
-                self.ctx.request_repaint();
-            } else if old_state.clone().unwrap().self_deaf != new_state.self_deaf {
-                if new_state.self_deaf {
-                    push_notification(&format!(
-                        "{} deafened themselves in {}",
-                        new_user.name.clone(),
-                        new_voice_channel.clone().unwrap().name.clone()
-                    ));
-                    play_sound();
-
-                    self.tx
-                        .send(ChannelMessage::UserDeafened(
-                            new_user.name.clone(),
-                            new_voice_channel.clone().unwrap().name.clone(),
-                        ))
-                        .await
-                        .unwrap();
-                    self.ctx.request_repaint();
-                } else {
-                    push_notification(&format!(
-                        "{} undeafened themselves in {}",
-                        new_user.name.clone(),
-                        new_voice_channel.clone().unwrap().name.clone()
-                    ));
-                    play_sound();
-
-                    self.tx
-                        .send(ChannelMessage::UserUndeafened(
-                            new_user.name.clone(),
-                            new_voice_channel.clone().unwrap().name.clone(),
-                        ))
-                        .await
-                        .unwrap();
-                    self.ctx.request_repaint();
-                }
-            } else if old_state.clone().unwrap().self_mute != new_state.self_mute {
-                if new_state.self_mute {
-                    push_notification(&format!(
-                        "{} muted themselves in {}",
-                        new_user.name.clone(),
-                        new_voice_channel.clone().unwrap().name.clone()
-                    ));
-                    play_sound();
-
-                    self.tx
-                        .send(ChannelMessage::UserMuted(
-                            new_user.name.clone(),
-                            new_voice_channel.clone().unwrap().name.clone(),
-                        ))
-                        .await
-                        .unwrap();
-                    self.ctx.request_repaint();
-                } else {
-                    push_notification(&format!(
-                        "{} unmuted themselves in {}",
-                        new_user.name.clone(),
-                        new_voice_channel.clone().unwrap().name.clone()
-                    ));
-                    play_sound();
-
-                    self.tx
-                        .send(ChannelMessage::UserUnmuted(
-                            new_user.name.clone(),
-                            new_voice_channel.clone().unwrap().name.clone(),
-                        ))
-                        .await
-                        .unwrap();
-                    self.ctx.request_repaint();
+            let moderator = match kind {
+                VoiceEventKind::ServerMuted
+                | VoiceEventKind::ServerUnmuted
+                | VoiceEventKind::ServerDeafened
+                | VoiceEventKind::ServerUndeafened => {
+                    api.moderator_name(guild_id, new_state.user_id, kind).await
                 }
-            }
-        } else if old_state.is_none() && new_state.channel_id.is_some() {
-            push_notification(&format!(
-                "{} joined {}",
-                new_user.name.clone(),
-                new_voice_channel.clone().unwrap().name.clone()
-            ));
-            play_sound();
-
-            self.tx
-                .send(ChannelMessage::UserJoinedChannel(
-                    new_user.name.clone(),
-                    new_voice_channel.clone().unwrap().name.clone(),
-                ))
-                .await
-                .unwrap();
-            self.ctx.request_repaint();
-        } else {
-            self.tx
-                .send(ChannelMessage::Custom(format!(
-                    "Unknown event:\n\told_state: {:?}\n\tnew_state: {:?}",
-                    old_state, new_state
-                )))
-                .await
-                .unwrap();
-            self.ctx.request_repaint();
+                _ => None,
+            };
+
+            self.dispatch(
+                VoiceEvent {
+                    timestamp: time,
+                    kind,
+                    guild_id,
+                    guild_name: guild_name.clone(),
+                    user_id: new_state.user_id,
+                    user_name: user_name.clone(),
+                    roles: roles.clone(),
+                    channel_id,
+                    channel_name,
+                    from_channel_id,
+                    from_channel_name,
+                    before,
+                    after,
+                    moderator,
+                    resynced: false,
+                },
+                true,
+            )
+            .await?;
         }
 
-        *OLD_STATE.lock().await = Some(new_state.clone());
+        Ok(())
+    }
+
+    /// Starts the voice state sync of the guilds of `ready`.
+    pub async fn on_ready(&self, ready: Ready) {
+        // The guilds follow in `guild_create`
+        let is_reconnect = {
+            let voice_states = self.voice_states.lock().await;
+            ready
+                .guilds
+                .iter()
+                .any(|guild| voice_states.is_synced(guild.id))
+        };
+        *self.sync.lock().await = Some(SyncProgress {
+            pending: ready.guilds.iter().map(|guild| guild.id).collect(),
+            guilds: ready.guilds.len(),
+            is_reconnect,
+            ..SyncProgress::default()
+        });
+
+        if let Err(e) = self.send(ChannelMessage::BotConnected(Box::new(ready))) {
+            self.report(e);
+        }
+    }
+
+    /// Syncs the voice states of `guild`, the events are stamped with `time`,
+    /// when the payload was received.
+    pub async fn on_guild_create(&self, api: &dyn DiscordApi, guild: Guild, time: DateTime<Utc>) {
+        // One failing guild doesn't keep the others from being synced
+        let guild_id = guild.id;
+        if let Err(e) = self.sync_guild(api, guild, time).await {
+            self.report(e);
+            self.finish_sync(guild_id, false, 0).await;
+        }
+    }
+
+    /// Reports the changes from `old_state` to `new_state`, the events are
+    /// stamped with `time`, when the payload was received.
+    pub async fn on_voice_state_update(
+        &self,
+        api: &dyn DiscordApi,
+        old_state: Option<VoiceState>,
+        new_state: VoiceState,
+        time: DateTime<Utc>,
+    ) {
+        if let Err(e) = self
+            .handle_voice_state_update(api, old_state, new_state, time)
+            .await
+        {
+            self.report(e);
+        }
+    }
+}
+
+#[async_trait]
+impl EventHandler for DiscordEventHandler {
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        ctx.set_presence(None, OnlineStatus::Invisible).await;
+        self.on_ready(ready).await;
+    }
+
+    async fn shard_stage_update(&self, _ctx: Context, update: ShardStageUpdateEvent) {
+        let state = match update.new {
+            ConnectionStage::Connected => ConnectionState::Connected,
+            ConnectionStage::Resuming => ConnectionState::Resuming,
+            ConnectionStage::Disconnected => ConnectionState::Disconnected,
+            _ => ConnectionState::Connecting,
+        };
+        if let Err(e) = self.send(ChannelMessage::Connection(state)) {
+            self.report(e);
+        }
+    }
+
+    async fn guild_create(&self, ctx: Context, guild: Guild, _is_new: bool) {
+        self.on_guild_create(&ctx, guild, Utc::now()).await;
+    }
+
+    async fn voice_state_update(
+        &self,
+        ctx: Context,
+        old_state: Option<VoiceState>,
+        new_state: VoiceState,
+    ) {
+        self.on_voice_state_update(&ctx, old_state, new_state, Utc::now())
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{voice_state, with_flags, ALICE, GAMING, GENERAL};
+
+    fn state(channel_id: Option<u64>, flags: &[(&str, bool)]) -> VoiceState {
+        serde_json::from_value(with_flags(voice_state(ALICE, channel_id), flags)).unwrap()
+    }
+
+    #[test]
+    fn reports_each_flag_flip() {
+        for (flag, on, off) in [
+            ("self_mute", VoiceEventKind::Muted, VoiceEventKind::Unmuted),
+            (
+                "self_deaf",
+                VoiceEventKind::Deafened,
+                VoiceEventKind::Undeafened,
+            ),
+            (
+                "self_stream",
+                VoiceEventKind::StartedStreaming,
+                VoiceEventKind::StoppedStreaming,
+            ),
+            (
+                "self_video",
+                VoiceEventKind::CameraOn,
+                VoiceEventKind::CameraOff,
+            ),
+            (
+                "mute",
+                VoiceEventKind::ServerMuted,
+                VoiceEventKind::ServerUnmuted,
+            ),
+            (
+                "deaf",
+                VoiceEventKind::ServerDeafened,
+                VoiceEventKind::ServerUndeafened,
+            ),
+            (
+                "suppress",
+                VoiceEventKind::Suppressed,
+                VoiceEventKind::Unsuppressed,
+            ),
+        ] {
+            let unset = state(Some(GENERAL), &[]);
+            let set = state(Some(GENERAL), &[(flag, true)]);
+
+            assert_eq!(diff_voice_states(Some(&unset), &set), vec![on], "{}", flag);
+            assert_eq!(diff_voice_states(Some(&set), &unset), vec![off], "{}", flag);
+            assert_eq!(diff_voice_states(Some(&set), &set), vec![], "{}", flag);
+        }
+    }
+
+    #[test]
+    fn reports_channel_changes_without_flags() {
+        let muted = [("self_mute", true)];
+        for (old, new, expected) in [
+            (
+                None,
+                state(Some(GENERAL), &muted),
+                vec![VoiceEventKind::Joined],
+            ),
+            (
+                Some(state(None, &[])),
+                state(Some(GENERAL), &muted),
+                vec![VoiceEventKind::Joined],
+            ),
+            (
+                Some(state(Some(GENERAL), &[])),
+                state(None, &muted),
+                vec![VoiceEventKind::Left],
+            ),
+            (
+                Some(state(Some(GENERAL), &[])),
+                state(Some(GAMING), &[]),
+                vec![VoiceEventKind::Moved],
+            ),
+            (None, state(None, &[]), vec![]),
+        ] {
+            assert_eq!(
+                diff_voice_states(old.as_ref(), &new),
+                expected,
+                "{:?} to {:?}",
+                old.as_ref().and_then(|old| old.channel_id),
+                new.channel_id
+            );
+        }
+    }
+
+    #[test]
+    fn reports_several_changes_in_order() {
+        let old = state(Some(GENERAL), &[("self_mute", true), ("self_video", true)]);
+        let new = state(
+            Some(GAMING),
+            &[("self_deaf", true), ("self_stream", true), ("mute", true)],
+        );
+
+        assert_eq!(
+            diff_voice_states(Some(&old), &new),
+            vec![
+                VoiceEventKind::Moved,
+                VoiceEventKind::Unmuted,
+                VoiceEventKind::Deafened,
+                VoiceEventKind::StartedStreaming,
+                VoiceEventKind::CameraOff,
+                VoiceEventKind::ServerMuted,
+            ]
+        );
     }
 }
@@ -1,114 +1,377 @@
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
 };
 
+use chrono::{DateTime, Local, Utc};
 use egui::{
-    vec2, Align2, Button, Color32, FontDefinitions, FontId, Id, LayerId, Margin, RichText,
-    ScrollArea, Sense, Shadow, Stroke, Ui, UiStackInfo, ViewportBuilder, ViewportCommand,
+    vec2, Align2, Button, CollapsingHeader, Color32, FontDefinitions, FontId, Id, LayerId, Margin,
+    RichText, ScrollArea, Sense, Shadow, Stroke, Ui, UiStackInfo, ViewportBuilder, ViewportCommand,
     ViewportId,
 };
-use egui_struct::EguiStruct;
-use tokio::sync::mpsc::Receiver;
+use serenity::model::id::GuildId;
+
+use crate::{
+    audio::AudioPlayer,
+    channel::{message_channel, MessageReceiver},
+    config::{Config, NotificationSettings, TokenSource, UiSettings, WindowGeometry},
+    discord::{DesktopAlerts, DiscordClient, DiscordEventHandler},
+    enums::{ChannelMessage, ConnectionState, LogEntry, VoiceEventKind},
+    gateway::{spawn_replay, Replay},
+    models::{Roster, VoiceFlags, WatchFilter},
+    rules::RuleSet,
+    storage::EventStore,
+    token::{forget_token, load_token, store_token, TokenLocation},
+};
 
-use crate::{discord::CustomVoiceState, enums::ChannelMessage};
+use super::{
+    debug_inspector::DebugInspector, export_window::ExportWindow, rules_window::RulesWindow,
+    settings_window::SettingsWindow, sounds_window::SoundsWindow,
+    statistics_window::StatisticsWindow, token_window::TokenWindow, top_bar::render_top_bar,
+};
 
-use super::top_bar::render_top_bar;
+/// Number of stored events loaded at a time.
+const HISTORY_PAGE_SIZE: usize = 100;
 
-#[derive(Debug, Clone)]
-struct DebugVoiceState {
-    old_state: CustomVoiceState,
-    new_state: CustomVoiceState,
-}
+/// Color of Discord's "LIVE" badge.
+const LIVE_COLOR: Color32 = Color32::from_rgb(237, 66, 69);
 
 pub struct AppModel {
     pub bot_name: Option<String>,
+    pub connection: ConnectionState,
+    /// Last heartbeat latency, `None` until the first heartbeat.
+    pub latency: Option<Duration>,
+    client: DiscordClient,
+    pub token_window: TokenWindow,
+
+    pub events: Vec<LogEntry>,
+    history: Option<EventStore>,
+    oldest_event_id: Option<i64>,
+    has_more_history: bool,
+    pub statistics_window: StatisticsWindow,
+    pub export_window: ExportWindow,
+
+    pub guilds: Vec<(GuildId, String)>,
+    pub watch_filter: Arc<RwLock<WatchFilter>>,
 
-    pub events: Vec<String>,
+    pub rules: Arc<RwLock<RuleSet>>,
+    pub rules_window: RulesWindow,
+
+    pub audio: AudioPlayer,
+    pub sounds_window: SoundsWindow,
+
+    pub notifications: Arc<RwLock<NotificationSettings>>,
+    pub config: Config,
+    pub settings_window: SettingsWindow,
+    /// Last known position and size of the window, saved on exit.
+    window_geometry: Option<WindowGeometry>,
+
+    pub is_roster_visible: bool,
 
     pub is_always_on_top: bool,
     pub is_custom_frame: bool,
 
     pub show_debug_info: Arc<AtomicBool>,
-    debug_events: Vec<DebugVoiceState>,
+    /// Shared with the debug viewport, drawn after this one.
+    debug_inspector: Arc<Mutex<DebugInspector>>,
 
-    rx: Receiver<ChannelMessage>,
+    rx: MessageReceiver,
+    /// Messages lost because the UI fell behind, see [`crate::channel`].
+    pub dropped_messages: usize,
 }
 
 impl AppModel {
-    pub fn new(cc: &eframe::CreationContext<'_>, rx: Receiver<ChannelMessage>) -> Self {
+    pub fn new(
+        cc: &eframe::CreationContext<'_>,
+        watch_filter: Arc<RwLock<WatchFilter>>,
+        rules: Arc<RwLock<RuleSet>>,
+        audio: AudioPlayer,
+        notifications: Arc<RwLock<NotificationSettings>>,
+        config: Config,
+        replay: Option<Replay>,
+    ) -> Self {
         let mut fonts = FontDefinitions::default();
         egui_phosphor::add_to_fonts(&mut fonts, egui_phosphor::Variant::Regular);
         cc.egui_ctx.set_fonts(fonts);
 
-        AppModel {
+        let history = match EventStore::open() {
+            Ok(store) => Some(store),
+            Err(e) => {
+                eprintln!("Error when opening event history: {}", e);
+                None
+            }
+        };
+
+        let (tx, rx) = message_channel(Some(cc.egui_ctx.clone()));
+        let client = DiscordClient::new(config.overrides.record.clone(), {
+            let tx = tx.clone();
+            let watch_filter = watch_filter.clone();
+            let rules = rules.clone();
+            let audio = audio.clone();
+            let notifications = notifications.clone();
+            move || {
+                DiscordEventHandler::new(
+                    tx.clone(),
+                    watch_filter.clone(),
+                    rules.clone(),
+                    audio.clone(),
+                    notifications.clone(),
+                )
+            }
+        });
+
+        // * Replays don't go to the history, it already has them
+        if let Some(replay) = replay.clone() {
+            let mut handler = DiscordEventHandler::with_sinks(
+                tx.clone(),
+                watch_filter.clone(),
+                rules.clone(),
+                Arc::new(DesktopAlerts::new(
+                    audio.clone(),
+                    notifications.clone(),
+                    tx.clone(),
+                )),
+                None,
+            );
+            handler.roster = client.roster.clone();
+            spawn_replay(handler, replay);
+        }
+
+        let mut app = AppModel {
             bot_name: None,
+            connection: ConnectionState::default(),
+            latency: None,
+            client,
+            token_window: TokenWindow::default(),
             events: vec![],
+            history,
+            oldest_event_id: None,
+            has_more_history: true,
+            statistics_window: StatisticsWindow::default(),
+            export_window: ExportWindow::default(),
+
+            guilds: vec![],
+            watch_filter,
+
+            rules,
+            rules_window: RulesWindow::default(),
+
+            audio,
+            sounds_window: SoundsWindow::default(),
 
-            is_always_on_top: false,
-            is_custom_frame: false,
+            notifications,
+            settings_window: SettingsWindow::default(),
+            window_geometry: config.window,
 
-            show_debug_info: Arc::new(AtomicBool::new(false)),
-            debug_events: vec![],
+            is_roster_visible: config.ui.roster,
+
+            is_always_on_top: config.ui.always_on_top,
+            is_custom_frame: config.ui.custom_frame,
+
+            show_debug_info: Arc::new(AtomicBool::new(config.ui.debug)),
+            debug_inspector: Arc::default(),
+
+            config,
 
             rx,
+            dropped_messages: 0,
+        };
+        app.load_older_events();
+
+        // * Connect, or ask for the token on the first run
+        if replay.is_none() {
+            match load_token(&app.config.token) {
+                Some(token) => app.client.start(token),
+                None => app.token_window.open(),
+            }
         }
+
+        app
     }
-}
 
-impl eframe::App for AppModel {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        if let Ok(rx) = self.rx.try_recv() {
-            match rx {
-                ChannelMessage::BotConnected(ready) => {
-                    self.bot_name = Some(ready.user.name);
-                }
-                ChannelMessage::UserJoinedChannel(name, channel) => {
-                    self.events
-                        .insert(0, format!("{} joined {}", name, channel));
-                }
-                ChannelMessage::UserAlreadyInChannel(name, channel) => {
-                    self.events
-                        .insert(0, format!("{} is already in {}", name, channel));
-                }
-                ChannelMessage::UserLeftChannel(name, channel) => {
-                    self.events.insert(0, format!("{} left {}", name, channel));
-                }
-                ChannelMessage::UserDeafened(name, channel) => {
-                    self.events
-                        .insert(0, format!("{} deafened in {}", name, channel));
-                }
-                ChannelMessage::UserUndeafened(name, channel) => {
-                    self.events
-                        .insert(0, format!("{} undeafened in {}", name, channel));
-                }
-                ChannelMessage::UserMuted(name, channel) => {
-                    self.events
-                        .insert(0, format!("{} muted in {}", name, channel));
-                }
-                ChannelMessage::UserUnmuted(name, channel) => {
-                    self.events
-                        .insert(0, format!("{} unmuted in {}", name, channel));
+    /// Saves a token entered in the token window and reconnects with it.
+    fn set_token(&mut self, token: String) {
+        let location = match store_token(&token) {
+            Ok(location) => location,
+            Err(e) => {
+                self.token_window.status = Some(Err(format!("Error when saving token: {}", e)));
+                return;
+            }
+        };
+
+        self.config.token = TokenSource::Keyring;
+        if let Err(e) = self.save_config() {
+            eprintln!("{}", e);
+        }
+        self.token_window.status = Some(Ok(match location {
+            TokenLocation::Keyring => "Saved in the Secret Service".to_owned(),
+            TokenLocation::File(path) => format!("Saved to {}", path.display()),
+        }));
+
+        self.bot_name = None;
+        self.client.start(token);
+    }
+
+    pub fn open_statistics(&mut self) {
+        self.statistics_window
+            .open(self.history.as_ref(), &self.client);
+    }
+
+    /// Reconnects with the current token, asking for one when there is none.
+    pub fn reconnect(&mut self) {
+        if !self.client.reconnect() {
+            self.token_window.open();
+        }
+    }
+
+    /// Disconnects and removes the stored token, asking for a new one.
+    pub fn forget_token(&mut self) {
+        if let Err(e) = forget_token() {
+            eprintln!("Error when removing token: {}", e);
+        }
+
+        self.config.token = TokenSource::Keyring;
+        if let Err(e) = self.save_config() {
+            eprintln!("{}", e);
+        }
+
+        self.client.stop();
+        self.client.save_open_sessions();
+        self.bot_name = None;
+        *self.client.roster.write().unwrap() = Roster::default();
+        self.token_window.open();
+    }
+
+    /// Writes the current settings to the config file.
+    pub fn save_config(&mut self) -> Result<(), String> {
+        self.config.ui = UiSettings {
+            always_on_top: self.is_always_on_top,
+            custom_frame: self.is_custom_frame,
+            roster: self.is_roster_visible,
+            debug: self.show_debug_info.load(Ordering::Relaxed),
+        };
+        self.config.filter = self.watch_filter.read().unwrap().clone();
+        self.config.notifications = *self.notifications.read().unwrap();
+        self.config.sounds = self.audio.settings.read().unwrap().clone();
+        if self.config.remember_window {
+            self.config.window = self.window_geometry;
+        }
+
+        self.config
+            .save()
+            .map_err(|e| format!("Error when saving config: {}", e))
+    }
+
+    pub fn clear_events(&mut self) {
+        self.events.clear();
+        self.has_more_history = false;
+    }
+
+    /// Appends the next page of stored events to the bottom of the list.
+    fn load_older_events(&mut self) {
+        let history = match self.history {
+            Some(ref history) if self.has_more_history => history,
+            _ => return,
+        };
+
+        let page = match self.oldest_event_id {
+            Some(id) => history.before(id, HISTORY_PAGE_SIZE),
+            None => history.latest(HISTORY_PAGE_SIZE),
+        };
+        match page {
+            Ok(page) => {
+                self.has_more_history = page.len() == HISTORY_PAGE_SIZE;
+                if let Some(oldest) = page.last() {
+                    self.oldest_event_id = Some(oldest.id);
                 }
-                ChannelMessage::UserMoved(name, old_channel, new_channel) => {
-                    self.events.insert(
-                        0,
-                        format!("{} moved from {} to {}", name, old_channel, new_channel),
-                    );
+                // The history has every category, show the watched ones
+                let watch_filter = self.watch_filter.read().unwrap();
+                self.events.extend(
+                    page.into_iter()
+                        .filter(|stored| {
+                            watch_filter.is_category_enabled(stored.event.kind.category())
+                        })
+                        .map(|stored| LogEntry::Voice(stored.event)),
+                );
+            }
+            Err(e) => {
+                eprintln!("Error when loading event history: {}", e);
+                self.has_more_history = false;
+            }
+        }
+    }
+
+    fn apply_message(&mut self, message: ChannelMessage) {
+        match message {
+            ChannelMessage::BotConnected(ready) => {
+                self.bot_name = Some(ready.user.name);
+            }
+            ChannelMessage::GuildAvailable(guild_id, name) => {
+                match self.guilds.iter_mut().find(|(id, _)| *id == guild_id) {
+                    Some(guild) => guild.1 = name,
+                    None => self.guilds.push((guild_id, name)),
                 }
-                ChannelMessage::Custom(event) => {
-                    self.events.insert(0, event);
+            }
+            ChannelMessage::Voice(event) => {
+                self.events.insert(0, LogEntry::Voice(event));
+            }
+            ChannelMessage::Custom(event) => {
+                self.events.insert(0, LogEntry::Custom(event));
+            }
+            ChannelMessage::Error(error) => {
+                self.events.insert(0, LogEntry::Error(error));
+            }
+            ChannelMessage::Connection(state) => {
+                self.connection = state;
+                if state != ConnectionState::Connected {
+                    self.latency = None;
                 }
+            }
+            ChannelMessage::Latency(latency) => {
+                self.latency = Some(latency);
+            }
 
-                ChannelMessage::DebugData(old_state, new_state) => {
-                    self.debug_events.push(DebugVoiceState {
-                        old_state,
-                        new_state,
-                    });
-                }
+            ChannelMessage::DebugData(update) => {
+                self.debug_inspector.lock().unwrap().push(*update);
             }
+        }
+    }
+}
+
+impl eframe::App for AppModel {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let geometry = ctx.input(|i| {
+            let viewport = i.viewport();
+            let position = viewport.outer_rect?.min;
+            let size = viewport.inner_rect?.size();
+            Some(WindowGeometry {
+                x: position.x,
+                y: position.y,
+                width: size.x,
+                height: size.y,
+            })
+        });
+        if geometry.is_some() {
+            self.window_geometry = geometry;
+        }
 
-            ctx.request_repaint();
+        while let Ok(message) = self.rx.try_recv() {
+            self.apply_message(message);
+        }
+        let dropped = self.rx.take_dropped();
+        if dropped > 0 {
+            self.dropped_messages += dropped;
+            self.events.insert(
+                0,
+                LogEntry::Error(format!(
+                    "{} messages dropped while the window was not responding, voice events are \
+                     still in the history",
+                    dropped
+                )),
+            );
         }
 
         // ! Render events
@@ -166,38 +429,116 @@ impl eframe::App for AppModel {
 
                 ui.separator();
 
+                // ! Roster
+                egui::SidePanel::right("roster_panel")
+                    .resizable(true)
+                    .default_width(140.)
+                    .show_animated_inside(ui, self.is_roster_visible, |ui| {
+                        render_roster(ui, &self.client.roster.read().unwrap());
+                    });
+
                 // ! Events list
-                ScrollArea::new([false, true])
+                let scroll = ScrollArea::new([false, true])
                     .auto_shrink([false; 2])
                     .drag_to_scroll(true)
                     .show(ui, |ui| {
                         let font_size = 16.;
                         for event in self.events.iter() {
-                            let text = if event.contains("joined") {
-                                RichText::new(event).strong()
-                            } else if event.contains("left") {
-                                RichText::new(event).strikethrough()
-                            } else {
-                                RichText::new(event).small()
+                            let text = match event {
+                                LogEntry::Voice(event) => {
+                                    let text = RichText::new(format!(
+                                        "{} {} [{}] {}",
+                                        format_timestamp(event.timestamp),
+                                        event_icon(event.kind),
+                                        event.guild_name,
+                                        event.description()
+                                    ));
+                                    match event.kind {
+                                        VoiceEventKind::Joined | VoiceEventKind::AlreadyIn => {
+                                            text.strong()
+                                        }
+                                        VoiceEventKind::Left => text.strikethrough(),
+                                        VoiceEventKind::StartedStreaming => {
+                                            text.strong().color(LIVE_COLOR)
+                                        }
+                                        VoiceEventKind::CameraOn => text.strong(),
+                                        VoiceEventKind::ServerMuted
+                                        | VoiceEventKind::ServerDeafened
+                                        | VoiceEventKind::Suppressed => text.italics(),
+                                        _ => text.small(),
+                                    }
+                                }
+                                LogEntry::Custom(event) => RichText::new(event).small(),
+                                LogEntry::Error(error) => {
+                                    RichText::new(error).small().color(Color32::RED)
+                                }
                             };
                             ui.allocate_ui(vec2(ui.available_size_before_wrap().x, 15.), |ui| {
                                 ui.label(text.size(font_size));
                             });
                         }
                     });
+
+                // ! Page in older events once the bottom of the list is reached
+                let scrolled_to = scroll.state.offset.y + scroll.inner_rect.height();
+                if scrolled_to >= scroll.content_size.y - 30. {
+                    self.load_older_events();
+                }
             });
 
+        // ! Notification rules
+        if self.rules_window.is_open {
+            self.rules_window.show(ctx, &self.guilds, &self.rules);
+        }
+
+        // ! Bot token
+        if self.token_window.is_open {
+            if let Some(token) = self.token_window.show(ctx) {
+                self.set_token(token);
+            }
+        }
+
+        // ! Statistics
+        if self.statistics_window.is_open {
+            self.statistics_window
+                .show(ctx, self.history.as_ref(), &self.client);
+        }
+
+        // ! Export
+        if self.export_window.is_open {
+            let watch_filter = self.watch_filter.read().unwrap().clone();
+            self.export_window
+                .show(ctx, self.history.as_ref(), &watch_filter);
+        }
+
+        // ! Sound settings
+        if self.sounds_window.is_open && self.sounds_window.show(ctx, &self.audio) {
+            self.sounds_window.error = self.save_config().err();
+        }
+
+        // ! Settings
+        if self.settings_window.is_open
+            && self
+                .settings_window
+                .show(ctx, &mut self.config, &self.notifications)
+        {
+            self.settings_window.status = Some(
+                self.save_config()
+                    .map(|_| format!("Saved to {}", self.config.path.display())),
+            );
+        }
+
         // ! Debug info
         if self.show_debug_info.load(Ordering::Relaxed) {
             let show_deferred_viewport = self.show_debug_info.clone();
             let is_custom_frame = self.is_custom_frame;
-            let debug_events = self.debug_events.clone();
+            let debug_inspector = self.debug_inspector.clone();
 
             ctx.show_viewport_deferred(
                 ViewportId::from_hash_of("debug_info_viewport"),
                 ViewportBuilder::default()
                     .with_title("Debug Info")
-                    .with_inner_size([200.0, 100.0]),
+                    .with_inner_size([420.0, 360.0]),
                 move |ctx, class| {
                     assert!(
                         class == egui::ViewportClass::Deferred,
@@ -263,30 +604,8 @@ impl eframe::App for AppModel {
 
                             ui.separator();
 
-                            // ! Events list
-                            ScrollArea::new([false, true])
-                                .auto_shrink([false; 2])
-                                .drag_to_scroll(true)
-                                .show(ui, |ui| {
-                                    let font_size = 16.;
-                                    for (idx, event) in debug_events.iter().enumerate() {
-                                        event.clone().old_state.show_top(
-                                            ui,
-                                            RichText::new("Old State").strong().size(font_size),
-                                            None,
-                                        );
-
-                                        event.clone().new_state.show_top(
-                                            ui,
-                                            RichText::new("New State").strong().size(font_size),
-                                            None,
-                                        );
-
-                                        if idx < debug_events.len() - 1 {
-                                            ui.separator();
-                                        }
-                                    }
-                                });
+                            // ! Updates list
+                            debug_inspector.lock().unwrap().show(ui);
                         });
 
                     if ctx.input(|i| i.viewport().close_requested()) {
@@ -296,6 +615,108 @@ impl eframe::App for AppModel {
             );
         }
     }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.client.stop();
+        self.client.save_open_sessions();
+        if let Err(e) = self.save_config() {
+            eprintln!("{}", e);
+        }
+    }
+}
+
+fn render_roster(ui: &mut Ui, roster: &Roster) {
+    ScrollArea::vertical()
+        .id_source("roster_scroll")
+        .auto_shrink([false; 2])
+        .show(ui, |ui| {
+            if roster.guilds.is_empty() {
+                ui.label(RichText::new("Nobody in voice").weak());
+            }
+
+            for guild in roster.guilds.iter() {
+                CollapsingHeader::new(RichText::new(&guild.guild_name).strong())
+                    .id_source(guild.guild_id)
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        for channel in guild.channels.iter() {
+                            CollapsingHeader::new(format!(
+                                "{} {} ({})",
+                                egui_phosphor::regular::SPEAKER_HIGH,
+                                channel.channel_name,
+                                channel.members.len()
+                            ))
+                            .id_source(channel.channel_id)
+                            .default_open(true)
+                            .show(ui, |ui| {
+                                for member in channel.members.iter() {
+                                    ui.horizontal(|ui| {
+                                        ui.label(&member.user_name);
+                                        render_flag_icons(ui, member.flags);
+                                    });
+                                }
+                            });
+                        }
+                    });
+            }
+        });
+}
+
+fn render_flag_icons(ui: &mut Ui, flags: VoiceFlags) {
+    use egui_phosphor::regular;
+
+    if flags.self_stream {
+        ui.label(RichText::new(regular::BROADCAST).color(LIVE_COLOR))
+            .on_hover_text("Streaming");
+    }
+    if flags.self_video {
+        ui.label(regular::VIDEO_CAMERA).on_hover_text("Camera on");
+    }
+    if flags.mute {
+        ui.label(RichText::new(regular::MICROPHONE_SLASH).color(Color32::RED))
+            .on_hover_text("Server muted");
+    } else if flags.self_mute {
+        ui.label(regular::MICROPHONE_SLASH).on_hover_text("Muted");
+    }
+    if flags.deaf {
+        ui.label(RichText::new(regular::SPEAKER_SLASH).color(Color32::RED))
+            .on_hover_text("Server deafened");
+    } else if flags.self_deaf {
+        ui.label(regular::SPEAKER_SLASH).on_hover_text("Deafened");
+    }
+}
+
+fn format_timestamp(timestamp: DateTime<Utc>) -> String {
+    let timestamp = timestamp.with_timezone(&Local);
+    if timestamp.date_naive() == Local::now().date_naive() {
+        timestamp.format("%H:%M:%S").to_string()
+    } else {
+        timestamp.format("%d/%m %H:%M").to_string()
+    }
+}
+
+fn event_icon(kind: VoiceEventKind) -> &'static str {
+    use egui_phosphor::regular;
+
+    match kind {
+        VoiceEventKind::AlreadyIn => regular::USER,
+        VoiceEventKind::Joined => regular::SIGN_IN,
+        VoiceEventKind::Left => regular::SIGN_OUT,
+        VoiceEventKind::Moved => regular::ARROWS_LEFT_RIGHT,
+        VoiceEventKind::Muted => regular::MICROPHONE_SLASH,
+        VoiceEventKind::Unmuted => regular::MICROPHONE,
+        VoiceEventKind::Deafened => regular::SPEAKER_SLASH,
+        VoiceEventKind::Undeafened => regular::SPEAKER_HIGH,
+        VoiceEventKind::ServerMuted
+        | VoiceEventKind::ServerUnmuted
+        | VoiceEventKind::ServerDeafened
+        | VoiceEventKind::ServerUndeafened => regular::GAVEL,
+        VoiceEventKind::StartedStreaming => regular::BROADCAST,
+        VoiceEventKind::StoppedStreaming => regular::MONITOR,
+        VoiceEventKind::CameraOn => regular::VIDEO_CAMERA,
+        VoiceEventKind::CameraOff => regular::VIDEO_CAMERA_SLASH,
+        VoiceEventKind::Suppressed | VoiceEventKind::Unsuppressed => regular::MICROPHONE_STAGE,
+    }
 }
 
 fn title_bar_ui(ui: &mut Ui, title_bar_rect: egui::Rect, title: &str) {
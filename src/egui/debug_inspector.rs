@@ -0,0 +1,122 @@
+use std::collections::VecDeque;
+
+use chrono::Local;
+use egui::{CollapsingHeader, Color32, Grid, RichText, ScrollArea, TextEdit, Ui};
+
+use crate::discord::DebugUpdate;
+
+/// Updates kept in the inspector, older ones are dropped.
+const MAX_UPDATES: usize = 500;
+
+/// Lists the voice state updates received by the handler with the fields
+/// they changed and the voice state serenity parsed.
+#[derive(Default)]
+pub struct DebugInspector {
+    /// Newest first.
+    updates: VecDeque<DebugUpdate>,
+    filter: String,
+    /// Hide updates that changed none of the compared fields.
+    changed_only: bool,
+}
+
+impl DebugInspector {
+    pub fn push(&mut self, update: DebugUpdate) {
+        self.updates.push_front(update);
+        self.updates.truncate(MAX_UPDATES);
+    }
+
+    pub fn show(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label(egui_phosphor::regular::MAGNIFYING_GLASS);
+            ui.add(
+                TextEdit::singleline(&mut self.filter)
+                    .hint_text("User, guild or channel")
+                    .desired_width(160.),
+            );
+            ui.checkbox(&mut self.changed_only, "Changes only");
+            if ui.button("Clear").clicked() {
+                self.updates.clear();
+            }
+        });
+
+        ui.separator();
+
+        ScrollArea::new([false, true])
+            .auto_shrink([false; 2])
+            .show(ui, |ui| {
+                let updates = self
+                    .updates
+                    .iter()
+                    .filter(|update| self.filter.is_empty() || update.matches(&self.filter))
+                    .map(|update| (update, update.changed_fields()))
+                    .filter(|(_, changed)| !self.changed_only || !changed.is_empty())
+                    .collect::<Vec<_>>();
+                if updates.is_empty() {
+                    ui.label(RichText::new("No voice state updates yet").weak());
+                }
+
+                for (update, changed) in updates {
+                    render_update(ui, update, &changed);
+                }
+            });
+    }
+}
+
+fn render_update(ui: &mut Ui, update: &DebugUpdate, changed: &[&str]) {
+    let title = format!(
+        "{} {} @ {}  {}",
+        update.timestamp.with_timezone(&Local).format("%H:%M:%S"),
+        update.new_state.username,
+        update.new_state.guild_name,
+        if changed.is_empty() {
+            "no change".to_owned()
+        } else {
+            changed.join(", ")
+        }
+    );
+    CollapsingHeader::new(title)
+        .id_source((update.timestamp, &update.payload))
+        .show(ui, |ui| {
+            Grid::new(("debug_fields", update.timestamp, &update.payload))
+                .num_columns(3)
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label(RichText::new("Field").strong());
+                    ui.label(RichText::new("Old").strong());
+                    ui.label(RichText::new("New").strong());
+                    ui.end_row();
+
+                    for ((name, old), (_, new)) in update
+                        .old_state
+                        .fields()
+                        .into_iter()
+                        .zip(update.new_state.fields())
+                    {
+                        if changed.contains(&name) {
+                            ui.label(RichText::new(name).strong().color(Color32::YELLOW));
+                            ui.label(RichText::new(old).color(Color32::YELLOW));
+                            ui.label(RichText::new(new).color(Color32::YELLOW));
+                        } else {
+                            ui.label(RichText::new(name).weak());
+                            ui.label(RichText::new(old).weak());
+                            ui.label(RichText::new(new).weak());
+                        }
+                        ui.end_row();
+                    }
+                });
+
+            CollapsingHeader::new("Parsed voice state")
+                .id_source(("debug_payload", update.timestamp, &update.payload))
+                .show(ui, |ui| {
+                    ui.label(RichText::new(&update.payload).monospace());
+                });
+
+            if ui
+                .button(format!("{} Copy", egui_phosphor::regular::COPY))
+                .on_hover_text("Copy the update for a bug report")
+                .clicked()
+            {
+                ui.ctx().copy_text(update.report());
+            }
+        });
+}
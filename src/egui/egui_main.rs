@@ -1,37 +1,56 @@
-pub fn start_egui() -> eframe::Result<()> {
-    use std::env;
+pub fn start_egui(
+    config: crate::config::Config,
+    replay: Option<crate::gateway::Replay>,
+) -> eframe::Result<()> {
+    use std::sync::{Arc, RwLock};
 
-    use ::egui::{vec2, ViewportBuilder};
-    use dotenv::dotenv;
+    use ::egui::{pos2, vec2, ViewportBuilder, WindowLevel};
     use eframe::{icon_data::from_png_bytes, HardwareAcceleration};
-    use serenity::{prelude::GatewayIntents, Client};
-    use tokio::{runtime::Runtime, sync::mpsc};
+    use tokio::runtime::Runtime;
 
-    use crate::{discord::DiscordEventHandler, egui::app::AppModel, enums::ChannelMessage};
+    use crate::{audio::AudioPlayer, egui::app::AppModel, rules::RuleSet};
 
     // * Create tokio runtime
     let rt = Runtime::new().expect("Unable to create Runtime");
     let _enter = rt.enter();
 
-    // * Load discord token from .env
-    dotenv().ok();
-    let token = env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
+    // * Guild filter shared between the handler and the UI
+    let watch_filter = Arc::new(RwLock::new(config.filter.clone()));
 
-    // * Create channel
-    let (tx, rx) = mpsc::channel::<ChannelMessage>(1);
+    // * Load notification rules
+    let rules = Arc::new(RwLock::new(RuleSet::load()));
+
+    // * Start the audio thread
+    let audio = AudioPlayer::spawn(config.sounds.clone(), None);
+
+    // * Notification options shared between the handler and the UI
+    let notifications = Arc::new(RwLock::new(config.notifications));
 
     // * Initialize native options
+    let mut viewport = ViewportBuilder::default()
+        .with_inner_size(vec2(360.0, 360.0))
+        .with_min_inner_size(vec2(100.0, 100.0))
+        .with_transparent(true)
+        .with_transparent(true)
+        .with_decorations(!config.ui.custom_frame)
+        .with_window_level(if config.ui.always_on_top {
+            WindowLevel::AlwaysOnTop
+        } else {
+            WindowLevel::Normal
+        })
+        .with_icon(
+            from_png_bytes(&include_bytes!("../../assets/discord_watcher.png")[..])
+                .expect("Failed to load icon"),
+        );
+    let geometry = config.window.filter(|_| config.remember_window);
+    if let Some(geometry) = geometry {
+        viewport = viewport
+            .with_position(pos2(geometry.x, geometry.y))
+            .with_inner_size(vec2(geometry.width, geometry.height));
+    }
     let native_options = eframe::NativeOptions {
-        viewport: ViewportBuilder::default()
-            .with_inner_size(vec2(360.0, 360.0))
-            .with_min_inner_size(vec2(100.0, 100.0))
-            .with_transparent(true)
-            .with_transparent(true)
-            .with_icon(
-                from_png_bytes(&include_bytes!("../../assets/discord_watcher.png")[..])
-                    .expect("Failed to load icon"),
-            ),
-        centered: true,
+        viewport,
+        centered: geometry.is_none(),
         hardware_acceleration: HardwareAcceleration::Preferred,
 
         ..Default::default()
@@ -42,31 +61,15 @@ pub fn start_egui() -> eframe::Result<()> {
         "Discord Watcher",
         native_options,
         Box::new(|cc| {
-            // * Create GatewayIntents
-            let intents = GatewayIntents::GUILD_VOICE_STATES | GatewayIntents::GUILD_PRESENCES;
-            // | GatewayIntents::GUILD_MESSAGES
-            // | GatewayIntents::DIRECT_MESSAGES
-            // | GatewayIntents::MESSAGE_CONTENT;
-
-            // * Initiate event handler struct
-            let event_handler = DiscordEventHandler {
-                tx,
-                ctx: cc.egui_ctx.clone(),
-            };
-
-            // * Create Discord thread
-            tokio::spawn(async move {
-                let mut client = Client::builder(&token, intents)
-                    .event_handler(event_handler)
-                    .await
-                    .expect("Err creating client");
-
-                if let Err(why) = client.start_shards(1).await {
-                    eprintln!("Client error: {:?}", why);
-                }
-            });
-
-            Ok(Box::new(AppModel::new(cc, rx)))
+            Ok(Box::new(AppModel::new(
+                cc,
+                watch_filter,
+                rules,
+                audio,
+                notifications,
+                config,
+                replay,
+            )))
         }),
     )
 }
@@ -0,0 +1,195 @@
+use std::path::PathBuf;
+
+use chrono::{Local, Months};
+use egui::{Color32, Grid, RichText};
+
+use crate::{
+    enums::{ExportFormat, VoiceEventKind},
+    export::{export_events, parse_date, ExportQuery},
+    models::WatchFilter,
+    storage::EventStore,
+};
+
+/// Writes the event history for a range of days to a file.
+#[derive(Default)]
+pub struct ExportWindow {
+    pub is_open: bool,
+    format: ExportFormat,
+    path: String,
+    from: String,
+    to: String,
+    /// Export only the guilds and categories enabled in the menus.
+    use_filter: bool,
+    /// Names or IDs separated by commas.
+    users: String,
+    channels: String,
+    /// Every kind when empty.
+    kinds: Vec<VoiceEventKind>,
+    pub status: Option<Result<String, String>>,
+}
+
+impl ExportWindow {
+    /// Opens the window for `format`, the range defaults to the last month.
+    pub fn open(&mut self, format: ExportFormat) {
+        let today = Local::now().date_naive();
+        if self.from.is_empty() {
+            let month_ago = today.checked_sub_months(Months::new(1)).unwrap_or(today);
+            self.from = month_ago.format("%Y-%m-%d").to_string();
+        }
+        if self.to.is_empty() {
+            self.to = today.format("%Y-%m-%d").to_string();
+        }
+        let mut path = match self.path.trim() {
+            "" => dirs::home_dir()
+                .unwrap_or_default()
+                .join("discord_watcher_events"),
+            path => PathBuf::from(path),
+        };
+        path.set_extension(format.key());
+        self.path = path.display().to_string();
+        self.format = format;
+        self.use_filter = true;
+        self.status = None;
+        self.is_open = true;
+    }
+
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        history: Option<&EventStore>,
+        watch_filter: &WatchFilter,
+    ) {
+        let mut is_open = self.is_open;
+        let mut export = false;
+        let format = self.format;
+        egui::Window::new("Export")
+            .open(&mut is_open)
+            .default_size([340.0, 240.0])
+            .show(ctx, |ui| {
+                Grid::new("export_grid").num_columns(2).show(ui, |ui| {
+                    ui.label("Format");
+                    ui.horizontal(|ui| {
+                        for format in ExportFormat::ALL {
+                            ui.radio_value(&mut self.format, format, format.label());
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("File");
+                    ui.text_edit_singleline(&mut self.path);
+                    ui.end_row();
+
+                    ui.label("From");
+                    ui.text_edit_singleline(&mut self.from)
+                        .on_hover_text("First day, YYYY-MM-DD, empty for the oldest event");
+                    ui.end_row();
+
+                    ui.label("To");
+                    ui.text_edit_singleline(&mut self.to)
+                        .on_hover_text("Last day included, YYYY-MM-DD, empty for the newest event");
+                    ui.end_row();
+
+                    ui.label("Users");
+                    ui.text_edit_singleline(&mut self.users)
+                        .on_hover_text("Names or IDs separated by commas, empty for everyone");
+                    ui.end_row();
+
+                    ui.label("Channels");
+                    ui.text_edit_singleline(&mut self.channels)
+                        .on_hover_text("Names or IDs separated by commas, empty for every channel");
+                    ui.end_row();
+
+                    ui.label("Events");
+                    let kinds_text = match self.kinds.len() {
+                        0 => "All".to_owned(),
+                        1 => self.kinds[0].label().to_owned(),
+                        count => format!("{} kinds", count),
+                    };
+                    ui.menu_button(kinds_text, |ui| {
+                        for kind in VoiceEventKind::ALL {
+                            let is_selected = self.kinds.contains(&kind);
+                            let kind_text = format!(
+                                "{} {}",
+                                if is_selected {
+                                    egui_phosphor::regular::CHECK
+                                } else {
+                                    ""
+                                },
+                                kind.label()
+                            );
+                            if ui.button(kind_text).clicked() {
+                                if is_selected {
+                                    self.kinds.retain(|selected| *selected != kind);
+                                } else {
+                                    self.kinds.push(kind);
+                                }
+                            }
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Filter");
+                    ui.checkbox(&mut self.use_filter, "Watched guilds and events only");
+                    ui.end_row();
+                });
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    export = ui.button("Export").clicked();
+                    match self.status {
+                        Some(Ok(ref status)) => {
+                            ui.label(RichText::new(status).color(Color32::GREEN));
+                        }
+                        Some(Err(ref error)) => {
+                            ui.label(RichText::new(error).color(Color32::RED));
+                        }
+                        None => (),
+                    }
+                });
+            });
+        self.is_open = is_open;
+
+        // Keep the extension in line with the format
+        if self.format != format {
+            let mut path = PathBuf::from(&self.path);
+            path.set_extension(self.format.key());
+            self.path = path.display().to_string();
+        }
+        if export {
+            self.status = Some(self.export(history, watch_filter));
+        }
+    }
+
+    fn export(
+        &self,
+        history: Option<&EventStore>,
+        watch_filter: &WatchFilter,
+    ) -> Result<String, String> {
+        let date = |value: &str| match value.trim() {
+            "" => Ok(None),
+            value => parse_date(value).map(Some),
+        };
+        let names = |value: &str| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(str::to_owned)
+                .collect()
+        };
+        let query = ExportQuery {
+            from: date(&self.from)?,
+            to: date(&self.to)?,
+            filter: self.use_filter.then(|| watch_filter.clone()),
+            users: names(&self.users),
+            channels: names(&self.channels),
+            kinds: self.kinds.clone(),
+        };
+        let path = PathBuf::from(self.path.trim());
+        let history = history.ok_or("The event history is not available")?;
+        let count =
+            export_events(history, &query, self.format, &path).map_err(|e| e.to_string())?;
+        Ok(format!("Exported {} events to {}", count, path.display()))
+    }
+}
@@ -0,0 +1,291 @@
+use std::sync::{Arc, RwLock};
+
+use chrono::NaiveTime;
+use egui::{CollapsingHeader, Color32, Grid, RichText, Ui};
+use serenity::model::id::GuildId;
+
+use crate::{
+    enums::VoiceEventKind,
+    rules::{Rule, RuleAction, RuleSet, TimeWindow},
+};
+
+/// A rule being edited, with the text fields that still need parsing.
+struct RuleDraft {
+    rule: Rule,
+    channels: String,
+    users: String,
+    roles: String,
+    has_time: bool,
+    start: String,
+    end: String,
+}
+
+impl RuleDraft {
+    fn from_rule(rule: &Rule) -> Self {
+        let (start, end) = match rule.time {
+            Some(window) => (
+                window.start.format("%H:%M").to_string(),
+                window.end.format("%H:%M").to_string(),
+            ),
+            None => ("22:00".to_owned(), "07:00".to_owned()),
+        };
+
+        RuleDraft {
+            rule: rule.clone(),
+            channels: join_ids(rule.channels.iter().map(|id| id.0)),
+            users: join_ids(rule.users.iter().map(|id| id.0)),
+            roles: join_ids(rule.roles.iter().map(|id| id.0)),
+            has_time: rule.time.is_some(),
+            start,
+            end,
+        }
+    }
+
+    fn to_rule(&self) -> Result<Rule, String> {
+        let name = &self.rule.name;
+        let mut rule = self.rule.clone();
+        rule.channels = parse_ids(&self.channels)
+            .map_err(|e| format!("{}: invalid channel ID {}", name, e))?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        rule.users = parse_ids(&self.users)
+            .map_err(|e| format!("{}: invalid user ID {}", name, e))?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        rule.roles = parse_ids(&self.roles)
+            .map_err(|e| format!("{}: invalid role ID {}", name, e))?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        rule.time = if self.has_time {
+            Some(TimeWindow {
+                start: parse_time(&self.start)
+                    .ok_or_else(|| format!("{}: invalid start time {}", name, self.start))?,
+                end: parse_time(&self.end)
+                    .ok_or_else(|| format!("{}: invalid end time {}", name, self.end))?,
+            })
+        } else {
+            None
+        };
+
+        Ok(rule)
+    }
+}
+
+fn join_ids(ids: impl Iterator<Item = u64>) -> String {
+    ids.map(|id| id.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+fn parse_ids(text: &str) -> Result<Vec<u64>, String> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|id| !id.is_empty())
+        .map(|id| id.parse::<u64>().map_err(|_| id.to_owned()))
+        .collect()
+}
+
+fn parse_time(text: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(text.trim(), "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(text.trim(), "%H:%M:%S"))
+        .ok()
+}
+
+fn toggle<T: PartialEq + Copy>(ui: &mut Ui, items: &mut Vec<T>, item: T, label: &str) {
+    let mut checked = items.contains(&item);
+    if ui.checkbox(&mut checked, label).changed() {
+        if checked {
+            items.push(item);
+        } else {
+            items.retain(|i| *i != item);
+        }
+    }
+}
+
+/// Editor for the notification rules, saved to the rules file.
+#[derive(Default)]
+pub struct RulesWindow {
+    pub is_open: bool,
+    drafts: Vec<RuleDraft>,
+    status: Option<Result<String, String>>,
+}
+
+impl RulesWindow {
+    pub fn open(&mut self, rules: &RuleSet) {
+        self.drafts = rules.rules.iter().map(RuleDraft::from_rule).collect();
+        self.status = rules.load_error.clone().map(Err);
+        self.is_open = true;
+    }
+
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        guilds: &[(GuildId, String)],
+        rules: &Arc<RwLock<RuleSet>>,
+    ) {
+        let mut is_open = self.is_open;
+        egui::Window::new("Notification Rules")
+            .open(&mut is_open)
+            .default_size([340.0, 400.0])
+            .vscroll(true)
+            .show(ctx, |ui| {
+                ui.label(
+                    RichText::new(
+                        "The first matching rule decides, events no rule matches are notified.",
+                    )
+                    .small(),
+                );
+                ui.separator();
+
+                let mut swap = None;
+                let mut remove = None;
+                let count = self.drafts.len();
+                for (idx, draft) in self.drafts.iter_mut().enumerate() {
+                    ui.push_id(idx, |ui| {
+                        CollapsingHeader::new(draft.rule.name.clone()).show(ui, |ui| {
+                            render_draft(ui, draft, guilds);
+
+                            ui.horizontal(|ui| {
+                                if ui
+                                    .add_enabled(
+                                        idx > 0,
+                                        egui::Button::new(egui_phosphor::regular::ARROW_UP),
+                                    )
+                                    .clicked()
+                                {
+                                    swap = Some((idx - 1, idx));
+                                }
+                                if ui
+                                    .add_enabled(
+                                        idx + 1 < count,
+                                        egui::Button::new(egui_phosphor::regular::ARROW_DOWN),
+                                    )
+                                    .clicked()
+                                {
+                                    swap = Some((idx, idx + 1));
+                                }
+                                if ui.button(egui_phosphor::regular::TRASH).clicked() {
+                                    remove = Some(idx);
+                                }
+                            });
+                        });
+                    });
+                }
+                if let Some((a, b)) = swap {
+                    self.drafts.swap(a, b);
+                }
+                if let Some(idx) = remove {
+                    self.drafts.remove(idx);
+                }
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    if ui.button("Add rule").clicked() {
+                        self.drafts.push(RuleDraft::from_rule(&Rule::default()));
+                    }
+                    if ui.button("Save").clicked() {
+                        self.status = Some(self.save(rules));
+                    }
+                    if ui.button("Revert").clicked() {
+                        let saved = rules.read().unwrap().clone();
+                        self.open(&saved);
+                    }
+                });
+
+                match self.status {
+                    Some(Ok(ref message)) => {
+                        ui.label(message);
+                    }
+                    Some(Err(ref message)) => {
+                        ui.label(RichText::new(message).color(Color32::RED));
+                    }
+                    None => (),
+                }
+            });
+        self.is_open = is_open;
+    }
+
+    fn save(&self, rules: &Arc<RwLock<RuleSet>>) -> Result<String, String> {
+        let new_rules = RuleSet {
+            rules: self
+                .drafts
+                .iter()
+                .map(RuleDraft::to_rule)
+                .collect::<Result<_, _>>()?,
+            load_error: rules.read().unwrap().load_error.clone(),
+        };
+
+        new_rules
+            .save()
+            .map_err(|e| format!("Error when saving rules: {}", e))?;
+        *rules.write().unwrap() = new_rules;
+
+        Ok(format!("Saved to {}", RuleSet::path().display()))
+    }
+}
+
+fn render_draft(ui: &mut Ui, draft: &mut RuleDraft, guilds: &[(GuildId, String)]) {
+    Grid::new("rule_grid").num_columns(2).show(ui, |ui| {
+        ui.label("Name");
+        ui.text_edit_singleline(&mut draft.rule.name);
+        ui.end_row();
+
+        ui.label("Enabled");
+        ui.checkbox(&mut draft.rule.enabled, "");
+        ui.end_row();
+
+        ui.label("Events");
+        ui.vertical(|ui| {
+            ui.label(RichText::new("none = any").small());
+            for kind in VoiceEventKind::ALL {
+                toggle(ui, &mut draft.rule.kinds, kind, kind.label());
+            }
+        });
+        ui.end_row();
+
+        ui.label("Guilds");
+        ui.vertical(|ui| {
+            ui.label(RichText::new("none = any").small());
+            for (guild_id, name) in guilds {
+                toggle(ui, &mut draft.rule.guilds, *guild_id, name);
+            }
+        });
+        ui.end_row();
+
+        ui.label("Channel IDs");
+        ui.text_edit_singleline(&mut draft.channels);
+        ui.end_row();
+
+        ui.label("User IDs");
+        ui.text_edit_singleline(&mut draft.users);
+        ui.end_row();
+
+        ui.label("Role IDs");
+        ui.text_edit_singleline(&mut draft.roles);
+        ui.end_row();
+
+        ui.label("Time of day");
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut draft.has_time, "");
+            ui.add_enabled(
+                draft.has_time,
+                egui::TextEdit::singleline(&mut draft.start).desired_width(48.0),
+            );
+            ui.label("to");
+            ui.add_enabled(
+                draft.has_time,
+                egui::TextEdit::singleline(&mut draft.end).desired_width(48.0),
+            );
+        });
+        ui.end_row();
+
+        ui.label("Actions");
+        ui.vertical(|ui| {
+            for action in RuleAction::ALL {
+                toggle(ui, &mut draft.rule.actions, action, action.label());
+            }
+        });
+        ui.end_row();
+    });
+}
@@ -0,0 +1,145 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use egui::{Color32, DragValue, Grid, RichText};
+
+use crate::config::{Config, NotificationSettings, TokenSource};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum TokenChoice {
+    #[default]
+    Env,
+    File,
+    Keyring,
+}
+
+/// Editor for the options of the config file that have no menu of their own.
+#[derive(Default)]
+pub struct SettingsWindow {
+    pub is_open: bool,
+    token_choice: TokenChoice,
+    token_file: String,
+    notifications: NotificationSettings,
+    remember_window: bool,
+    pub status: Option<Result<String, String>>,
+}
+
+impl SettingsWindow {
+    pub fn open(&mut self, config: &Config, notifications: NotificationSettings) {
+        self.token_file.clear();
+        self.token_choice = match config.token {
+            TokenSource::Env => TokenChoice::Env,
+            TokenSource::File(ref path) => {
+                self.token_file = path.display().to_string();
+                TokenChoice::File
+            }
+            TokenSource::Keyring => TokenChoice::Keyring,
+        };
+        self.notifications = notifications;
+        self.remember_window = config.remember_window;
+        self.status = None;
+        self.is_open = true;
+    }
+
+    /// Shows the window, returns `true` once the changes are applied to
+    /// `config` and `notifications` and need saving.
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        config: &mut Config,
+        notifications: &Arc<RwLock<NotificationSettings>>,
+    ) -> bool {
+        let mut is_open = self.is_open;
+        let mut save = false;
+        egui::Window::new("Settings")
+            .open(&mut is_open)
+            .default_size([320.0, 200.0])
+            .show(ctx, |ui| {
+                Grid::new("settings_grid").num_columns(2).show(ui, |ui| {
+                    ui.label("Bot token");
+                    ui.vertical(|ui| {
+                        ui.radio_value(&mut self.token_choice, TokenChoice::Keyring, "Saved token");
+                        ui.radio_value(
+                            &mut self.token_choice,
+                            TokenChoice::Env,
+                            "DISCORD_TOKEN / .env",
+                        );
+                        ui.horizontal(|ui| {
+                            ui.radio_value(&mut self.token_choice, TokenChoice::File, "File");
+                            ui.add_enabled(
+                                self.token_choice == TokenChoice::File,
+                                egui::TextEdit::singleline(&mut self.token_file),
+                            );
+                        });
+                    });
+                    ui.end_row();
+
+                    ui.label("Desktop toasts");
+                    ui.checkbox(&mut self.notifications.enabled, "");
+                    ui.end_row();
+
+                    ui.label("Toast timeout");
+                    ui.add(
+                        DragValue::new(&mut self.notifications.timeout_ms)
+                            .range(100..=60_000)
+                            .suffix(" ms"),
+                    );
+                    ui.end_row();
+
+                    ui.label("Remember window");
+                    ui.checkbox(&mut self.remember_window, "");
+                    ui.end_row();
+                });
+
+                ui.label(
+                    RichText::new(
+                        "The token source applies on the next start. Guilds, events and the \
+                         Tools toggles are saved on exit.",
+                    )
+                    .small(),
+                );
+                ui.separator();
+
+                if ui.button("Save").clicked() {
+                    match self.token_source() {
+                        Ok(token) => {
+                            config.token = token;
+                            config.remember_window = self.remember_window;
+                            *notifications.write().unwrap() = self.notifications;
+                            save = true;
+                        }
+                        Err(e) => self.status = Some(Err(e)),
+                    }
+                }
+
+                match self.status {
+                    Some(Ok(ref message)) => {
+                        ui.label(message);
+                    }
+                    Some(Err(ref message)) => {
+                        ui.label(RichText::new(message).color(Color32::RED));
+                    }
+                    None => (),
+                }
+            });
+        self.is_open = is_open;
+
+        save
+    }
+
+    fn token_source(&self) -> Result<TokenSource, String> {
+        match self.token_choice {
+            TokenChoice::Env => return Ok(TokenSource::Env),
+            TokenChoice::Keyring => return Ok(TokenSource::Keyring),
+            TokenChoice::File => (),
+        }
+
+        let path = self.token_file.trim();
+        if path.is_empty() {
+            return Err("The token file is missing".to_owned());
+        }
+        Ok(TokenSource::File(PathBuf::from(path)))
+    }
+}
@@ -0,0 +1,154 @@
+use std::path::PathBuf;
+
+use egui::{Color32, ComboBox, Grid, RichText, Slider, Ui};
+
+use crate::{
+    audio::{AudioPlayer, SoundSettings},
+    enums::SoundKind,
+};
+
+/// Sound settings editor. Changes apply right away and are saved to the
+/// config file once the pointer is released.
+#[derive(Default)]
+pub struct SoundsWindow {
+    pub is_open: bool,
+    dir: String,
+    pack_files: Vec<PathBuf>,
+    is_dirty: bool,
+    pub error: Option<String>,
+}
+
+impl SoundsWindow {
+    pub fn open(&mut self, settings: &SoundSettings) {
+        self.dir = settings
+            .dir
+            .as_ref()
+            .map(|dir| dir.display().to_string())
+            .unwrap_or_default();
+        self.pack_files = settings.pack_files();
+        self.error = None;
+        self.is_open = true;
+    }
+
+    /// Shows the window, returns `true` when the changes need saving.
+    pub fn show(&mut self, ctx: &egui::Context, audio: &AudioPlayer) -> bool {
+        let mut is_open = self.is_open;
+        let mut reload = false;
+        let mut save = false;
+        egui::Window::new("Sounds")
+            .open(&mut is_open)
+            .default_size([340.0, 300.0])
+            .show(ctx, |ui| {
+                let mut settings = audio.settings.write().unwrap();
+
+                Grid::new("sounds_grid").num_columns(2).show(ui, |ui| {
+                    ui.label("Mute");
+                    self.is_dirty |= ui.checkbox(&mut settings.muted, "").changed();
+                    ui.end_row();
+
+                    ui.label("Volume");
+                    self.is_dirty |= ui
+                        .add(Slider::new(&mut settings.volume, 0.0..=1.0).custom_formatter(percent))
+                        .changed();
+                    ui.end_row();
+
+                    ui.label("Sound pack");
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut self.dir);
+                        if ui.button("Load").clicked() {
+                            let dir = self.dir.trim();
+                            settings.dir = (!dir.is_empty()).then(|| PathBuf::from(dir));
+                            self.pack_files = settings.pack_files();
+                            self.is_dirty = true;
+                            reload = true;
+                        }
+                    });
+                    ui.end_row();
+                });
+
+                ui.separator();
+
+                Grid::new("sound_kinds_grid").num_columns(4).show(ui, |ui| {
+                    for kind in SoundKind::ALL {
+                        ui.label(kind.label());
+                        reload |= self.render_picker(ui, &mut settings, kind);
+
+                        let mut volume = settings.kind_volume(kind);
+                        if ui
+                            .add(Slider::new(&mut volume, 0.0..=1.0).custom_formatter(percent))
+                            .changed()
+                        {
+                            settings.volumes.insert(kind, volume);
+                            self.is_dirty = true;
+                        }
+
+                        if ui.button(egui_phosphor::regular::PLAY).clicked() {
+                            audio.preview(kind);
+                        }
+                        ui.end_row();
+                    }
+                });
+
+                if let Some(ref error) = self.error {
+                    ui.label(RichText::new(error).color(Color32::RED));
+                }
+
+                // Save once a slider drag is over instead of on every frame
+                if self.is_dirty && !ctx.input(|i| i.pointer.any_down()) {
+                    self.is_dirty = false;
+                    save = true;
+                }
+            });
+        self.is_open = is_open;
+
+        if reload {
+            audio.reload();
+        }
+        save
+    }
+
+    /// Picks the file of `kind` among the pack files, returns whether it
+    /// changed.
+    fn render_picker(
+        &mut self,
+        ui: &mut Ui,
+        settings: &mut SoundSettings,
+        kind: SoundKind,
+    ) -> bool {
+        let selected = match settings.file(kind) {
+            Some(path) => path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            None => "Default".to_owned(),
+        };
+
+        let mut changed = false;
+        ComboBox::from_id_source(kind.key())
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                if ui.selectable_label(false, "Default").clicked() {
+                    settings.files.remove(&kind);
+                    settings.default_sounds.insert(kind);
+                    changed = true;
+                }
+                for file in self.pack_files.iter() {
+                    if ui
+                        .selectable_label(false, file.display().to_string())
+                        .clicked()
+                    {
+                        settings.files.insert(kind, file.clone());
+                        settings.default_sounds.remove(&kind);
+                        changed = true;
+                    }
+                }
+            });
+
+        self.is_dirty |= changed;
+        changed
+    }
+}
+
+fn percent(value: f64, _: std::ops::RangeInclusive<usize>) -> String {
+    format!("{:.0}%", value * 100.0)
+}
@@ -0,0 +1,244 @@
+use chrono::{Local, TimeDelta, Utc};
+use egui::{pos2, vec2, Align2, Color32, FontId, Grid, Rect, RichText, ScrollArea, Sense, Ui};
+
+use crate::{
+    discord::DiscordClient,
+    sessions::{Statistics, RECENT_DAYS},
+    storage::EventStore,
+    utils::format_duration,
+};
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Side of a heatmap cell.
+const CELL_SIZE: f32 = 14.0;
+
+/// Width of the weekday labels left of the heatmap.
+const LABEL_WIDTH: f32 = 32.0;
+
+/// Time in voice computed from the sessions of the event history and the
+/// ones still open.
+#[derive(Default)]
+pub struct StatisticsWindow {
+    pub is_open: bool,
+    statistics: Statistics,
+    pub error: Option<String>,
+}
+
+impl StatisticsWindow {
+    pub fn open(&mut self, history: Option<&EventStore>, client: &DiscordClient) {
+        self.refresh(history, client);
+        self.is_open = true;
+    }
+
+    fn refresh(&mut self, history: Option<&EventStore>, client: &DiscordClient) {
+        let now = Local::now();
+        let mut sessions = match history.map(|store| store.sessions_since(Statistics::since(&now)))
+        {
+            Some(Ok(sessions)) => sessions,
+            Some(Err(e)) => {
+                self.error = Some(format!("Error when reading sessions: {}", e));
+                return;
+            }
+            None => {
+                self.error = Some("The event history is not available".to_owned());
+                return;
+            }
+        };
+        sessions.extend(client.open_sessions(now.with_timezone(&Utc)));
+        self.statistics = Statistics::compute(&sessions, &now);
+        self.error = None;
+    }
+
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        history: Option<&EventStore>,
+        client: &DiscordClient,
+    ) {
+        let mut is_open = self.is_open;
+        let mut refresh = false;
+        egui::Window::new("Statistics")
+            .open(&mut is_open)
+            .default_size([420.0, 480.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    refresh = ui
+                        .button(egui_phosphor::regular::ARROWS_CLOCKWISE)
+                        .on_hover_text("Refresh")
+                        .clicked();
+                    ui.label(RichText::new("Sessions still open are counted up to now").weak());
+                });
+                if let Some(ref error) = self.error {
+                    ui.colored_label(Color32::RED, error);
+                }
+
+                ScrollArea::vertical()
+                    .auto_shrink([false; 2])
+                    .show(ui, |ui| {
+                        let statistics = &self.statistics;
+
+                        // ! Per user totals
+                        ui.heading("Time in voice");
+                        if statistics.users.is_empty() {
+                            ui.label(RichText::new("No sessions this month").weak());
+                        }
+                        Grid::new("statistics_users")
+                            .num_columns(4)
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for header in ["User", "Today", "Week", "Month"] {
+                                    ui.label(RichText::new(header).strong());
+                                }
+                                ui.end_row();
+
+                                for user in statistics.users.iter() {
+                                    ui.label(&user.user_name);
+                                    ui.label(format_duration(user.today));
+                                    ui.label(format_duration(user.week));
+                                    ui.label(format_duration(user.month));
+                                    ui.end_row();
+                                }
+                            });
+
+                        ui.separator();
+
+                        // ! Longest sessions
+                        ui.heading(format!("Longest sessions ({} days)", RECENT_DAYS));
+                        Grid::new("statistics_longest")
+                            .num_columns(4)
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for session in statistics.longest.iter() {
+                                    ui.label(&session.user_name);
+                                    ui.label(&session.channel_name)
+                                        .on_hover_text(&session.guild_name);
+                                    ui.label(
+                                        session
+                                            .start
+                                            .with_timezone(&Local)
+                                            .format("%Y-%m-%d %H:%M")
+                                            .to_string(),
+                                    );
+                                    ui.label(format_duration(session.duration())).on_hover_text(
+                                        format!(
+                                            "Muted {}, deafened {}, streaming {}",
+                                            format_duration(session.muted),
+                                            format_duration(session.deafened),
+                                            format_duration(session.streaming)
+                                        ),
+                                    );
+                                    ui.end_row();
+                                }
+                            });
+
+                        ui.separator();
+
+                        // ! Busiest channels
+                        ui.heading(format!("Busiest channels ({} days)", RECENT_DAYS));
+                        Grid::new("statistics_channels")
+                            .num_columns(3)
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for channel in statistics.channels.iter() {
+                                    ui.label(&channel.channel_name)
+                                        .on_hover_text(&channel.guild_name);
+                                    ui.label(format!("{} sessions", channel.sessions));
+                                    ui.label(format_duration(channel.time));
+                                    ui.end_row();
+                                }
+                            });
+
+                        ui.separator();
+
+                        // ! Activity heatmap
+                        ui.heading(format!("Activity ({} days)", RECENT_DAYS));
+                        render_heatmap(ui, &statistics.heatmap);
+                    });
+            });
+        self.is_open = is_open;
+
+        if refresh {
+            self.refresh(history, client);
+        }
+    }
+}
+
+/// Paints one cell per weekday and hour, brighter with more time in voice.
+fn render_heatmap(ui: &mut Ui, heatmap: &[[TimeDelta; 24]; 7]) {
+    let size = vec2(LABEL_WIDTH + CELL_SIZE * 24.0, CELL_SIZE * 8.0);
+    let (response, painter) = ui.allocate_painter(size, Sense::hover());
+    let origin = response.rect.min;
+    let max = heatmap
+        .iter()
+        .flatten()
+        .max()
+        .copied()
+        .unwrap_or_default()
+        .max(TimeDelta::seconds(1));
+
+    let visuals = ui.visuals();
+    let text_color = visuals.weak_text_color();
+    let empty_color = visuals.extreme_bg_color;
+    let full_color = visuals.selection.bg_fill;
+    let font = FontId::proportional(10.0);
+
+    // Hours every 3 hours along the top
+    for hour in (0..24).step_by(3) {
+        painter.text(
+            origin + vec2(LABEL_WIDTH + CELL_SIZE * hour as f32, CELL_SIZE / 2.0),
+            Align2::LEFT_CENTER,
+            hour.to_string(),
+            font.clone(),
+            text_color,
+        );
+    }
+
+    let mut hovered = None;
+    for (day, hours) in heatmap.iter().enumerate() {
+        let top = origin.y + CELL_SIZE * (day + 1) as f32;
+        painter.text(
+            pos2(origin.x, top + CELL_SIZE / 2.0),
+            Align2::LEFT_CENTER,
+            WEEKDAYS[day],
+            font.clone(),
+            text_color,
+        );
+
+        for (hour, time) in hours.iter().enumerate() {
+            let cell = Rect::from_min_size(
+                pos2(origin.x + LABEL_WIDTH + CELL_SIZE * hour as f32, top),
+                vec2(CELL_SIZE, CELL_SIZE),
+            )
+            .shrink(1.0);
+            let intensity = time.num_seconds() as f32 / max.num_seconds() as f32;
+            painter.rect_filled(cell, 2.0, lerp_color(empty_color, full_color, intensity));
+
+            if response
+                .hover_pos()
+                .map_or(false, |pointer| cell.contains(pointer))
+            {
+                hovered = Some((day, hour, *time));
+            }
+        }
+    }
+
+    if let Some((day, hour, time)) = hovered {
+        response.on_hover_text(format!(
+            "{} {:02}:00 - {:02}:00: {}",
+            WEEKDAYS[day],
+            hour,
+            (hour + 1) % 24,
+            format_duration(time)
+        ));
+    }
+}
+
+fn lerp_color(from: Color32, to: Color32, t: f32) -> Color32 {
+    let channel = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t.clamp(0.0, 1.0)) as u8;
+    Color32::from_rgb(
+        channel(from.r(), to.r()),
+        channel(from.g(), to.g()),
+        channel(from.b(), to.b()),
+    )
+}
@@ -0,0 +1,69 @@
+use egui::{Color32, RichText, TextEdit};
+
+use crate::token::validate_token;
+
+/// Asks for the bot token on the first run and when changing it.
+#[derive(Default)]
+pub struct TokenWindow {
+    pub is_open: bool,
+    token: String,
+    pub status: Option<Result<String, String>>,
+}
+
+impl TokenWindow {
+    pub fn open(&mut self) {
+        self.token.clear();
+        self.status = None;
+        self.is_open = true;
+    }
+
+    /// Shows the window, returns the token once it is entered and looks valid.
+    pub fn show(&mut self, ctx: &egui::Context) -> Option<String> {
+        let mut is_open = self.is_open;
+        let mut token = None;
+        egui::Window::new("Bot Token")
+            .open(&mut is_open)
+            .collapsible(false)
+            .default_width(320.0)
+            .show(ctx, |ui| {
+                ui.label(
+                    "Paste the token of your bot, from the Bot page of the application in the \
+                     Discord developer portal.",
+                );
+                ui.add(
+                    TextEdit::singleline(&mut self.token)
+                        .password(true)
+                        .hint_text("Bot token")
+                        .desired_width(f32::INFINITY),
+                );
+                ui.label(
+                    RichText::new(
+                        "It is kept in the Secret Service, or in a file only you can read when \
+                         there is none.",
+                    )
+                    .small(),
+                );
+
+                if ui.button("Save").clicked() {
+                    let entered = self.token.trim();
+                    match validate_token(entered) {
+                        Ok(()) => token = Some(entered.to_owned()),
+                        Err(e) => self.status = Some(Err(e)),
+                    }
+                }
+
+                match self.status {
+                    Some(Ok(ref message)) => {
+                        ui.label(message);
+                    }
+                    Some(Err(ref message)) => {
+                        ui.label(RichText::new(message).color(Color32::RED));
+                    }
+                    None => (),
+                }
+            });
+        self.is_open = is_open;
+
+        token
+    }
+}
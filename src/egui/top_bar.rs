@@ -1,6 +1,8 @@
 use std::sync::atomic::Ordering;
 
-use egui::{menu, RichText, Ui, ViewportCommand, WindowLevel};
+use egui::{menu, Color32, RichText, Ui, ViewportCommand, WindowLevel};
+
+use crate::enums::{ConnectionState, EventCategory, ExportFormat};
 
 use super::app::AppModel;
 
@@ -8,6 +10,29 @@ pub fn render_top_bar(app: &mut AppModel, ui: &mut Ui) {
     ui.add_enabled_ui(true, |ui| {
         menu::bar(ui, |ui| {
             ui.menu_button("App", |ui| {
+                if ui.button("Rules").clicked() {
+                    let rules = app.rules.read().unwrap().clone();
+                    app.rules_window.open(&rules);
+                    ui.close_menu();
+                }
+                if ui.button("Settings").clicked() {
+                    let notifications = *app.notifications.read().unwrap();
+                    app.settings_window.open(&app.config, notifications);
+                    ui.close_menu();
+                }
+                if ui.button("Sounds").clicked() {
+                    let settings = app.audio.settings.read().unwrap().clone();
+                    app.sounds_window.open(&settings);
+                    ui.close_menu();
+                }
+                if ui.button("Change Token").clicked() {
+                    app.token_window.open();
+                    ui.close_menu();
+                }
+                if ui.button("Forget Token").clicked() {
+                    app.forget_token();
+                    ui.close_menu();
+                }
                 if ui.button("Debug").clicked() {
                     app.show_debug_info.store(true, Ordering::Relaxed);
                     ui.close_menu();
@@ -21,8 +46,12 @@ pub fn render_top_bar(app: &mut AppModel, ui: &mut Ui) {
             ui.label("|");
 
             ui.menu_button("Tools", |ui| {
+                if ui.button("Statistics").clicked() {
+                    app.open_statistics();
+                    ui.close_menu();
+                }
                 if ui.button("Clear").clicked() {
-                    app.events.clear();
+                    app.clear_events();
                     ui.close_menu();
                 }
 
@@ -48,6 +77,36 @@ pub fn render_top_bar(app: &mut AppModel, ui: &mut Ui) {
                     ui.close_menu();
                 }
 
+                let roster_text = format!(
+                    "{} Roster",
+                    if app.is_roster_visible {
+                        egui_phosphor::regular::CHECK
+                    } else {
+                        ""
+                    }
+                );
+                if ui.button(roster_text).clicked() {
+                    app.is_roster_visible = !app.is_roster_visible;
+                    ui.close_menu();
+                }
+
+                let is_muted = app.audio.settings.read().unwrap().muted;
+                let mute_text = format!(
+                    "{} Mute Sounds",
+                    if is_muted {
+                        egui_phosphor::regular::CHECK
+                    } else {
+                        ""
+                    }
+                );
+                if ui.button(mute_text).clicked() {
+                    app.audio.settings.write().unwrap().muted = !is_muted;
+                    if let Err(e) = app.save_config() {
+                        eprintln!("{}", e);
+                    }
+                    ui.close_menu();
+                }
+
                 let clear_text = format!(
                     "{} Custom Frame",
                     if app.is_custom_frame {
@@ -66,12 +125,105 @@ pub fn render_top_bar(app: &mut AppModel, ui: &mut Ui) {
 
             ui.label("|");
 
+            ui.menu_button("Guilds", |ui| {
+                if app.guilds.is_empty() {
+                    ui.label("No guilds yet");
+                }
+
+                for (guild_id, name) in app.guilds.iter() {
+                    let is_enabled = app.watch_filter.read().unwrap().is_guild_enabled(*guild_id);
+                    let guild_text = format!(
+                        "{} {}",
+                        if is_enabled {
+                            egui_phosphor::regular::CHECK
+                        } else {
+                            ""
+                        },
+                        name
+                    );
+                    if ui.button(guild_text).clicked() {
+                        app.watch_filter
+                            .write()
+                            .unwrap()
+                            .set_guild_enabled(*guild_id, !is_enabled);
+                    }
+                }
+            });
+
+            ui.label("|");
+
+            ui.menu_button("Events", |ui| {
+                for category in EventCategory::ALL {
+                    let is_enabled = app
+                        .watch_filter
+                        .read()
+                        .unwrap()
+                        .is_category_enabled(category);
+                    let category_text = format!(
+                        "{} {}",
+                        if is_enabled {
+                            egui_phosphor::regular::CHECK
+                        } else {
+                            ""
+                        },
+                        category.label()
+                    );
+                    if ui.button(category_text).clicked() {
+                        app.watch_filter
+                            .write()
+                            .unwrap()
+                            .set_category_enabled(category, !is_enabled);
+                    }
+                }
+            });
+
+            ui.label("|");
+
+            ui.menu_button("Export", |ui| {
+                for format in ExportFormat::ALL {
+                    if ui.button(format!("{}…", format.label())).clicked() {
+                        app.export_window.open(format);
+                        ui.close_menu();
+                    }
+                }
+            });
+
+            ui.label("|");
+
+            let color = match app.connection {
+                ConnectionState::Connected => Color32::GREEN,
+                ConnectionState::Connecting | ConnectionState::Resuming => Color32::YELLOW,
+                ConnectionState::Disconnected | ConnectionState::AuthFailed => Color32::RED,
+            };
+            ui.label(RichText::new(egui_phosphor::regular::CIRCLE).color(color))
+                .on_hover_text(app.connection.label());
             match app.bot_name {
-                Some(ref name) => {
+                Some(ref name) if app.connection == ConnectionState::Connected => {
                     ui.label(RichText::new(format!("Bot connected ( {} )", name)).strong())
                 }
-                None => ui.label(RichText::new("Bot not connected").strikethrough()),
+                _ => ui.label(RichText::new(app.connection.label()).color(color)),
             };
+            if let Some(latency) = app.latency {
+                ui.label(format!("{} ms", latency.as_millis()));
+            }
+            if app.dropped_messages > 0 {
+                ui.label(
+                    RichText::new(format!(
+                        "{} {} dropped",
+                        egui_phosphor::regular::WARNING,
+                        app.dropped_messages
+                    ))
+                    .color(Color32::RED),
+                )
+                .on_hover_text("Messages lost while the window was not responding");
+            }
+            if ui
+                .button(egui_phosphor::regular::ARROWS_CLOCKWISE)
+                .on_hover_text("Reconnect")
+                .clicked()
+            {
+                app.reconnect();
+            }
         });
     });
 }
@@ -1,20 +1,316 @@
-use serenity::model::prelude::Ready;
+use std::time::Duration;
 
-use crate::discord::CustomVoiceState;
+use serde::{Deserialize, Serialize};
+use serenity::model::{id::GuildId, prelude::Ready};
+
+use crate::{discord::DebugUpdate, models::VoiceEvent};
 
 pub enum ChannelMessage {
     BotConnected(Box<Ready>),
-    UserJoinedChannel(String, String),
-    UserAlreadyInChannel(String, String),
-    UserMuted(String, String),
-    UserUnmuted(String, String),
-    UserDeafened(String, String),
-    UserUndeafened(String, String),
-    UserMoved(String, String, String),
-    UserLeftChannel(String, String),
+    GuildAvailable(GuildId, String),
+    Voice(VoiceEvent),
     Custom(String),
+    /// A failure of the Discord handler, already logged.
+    Error(String),
+    Connection(ConnectionState),
+    /// Last heartbeat round trip of the gateway.
+    Latency(Duration),
+
+    /// A voice state update for the debug inspector.
+    DebugData(Box<DebugUpdate>),
+}
+
+/// State of the gateway connection, as shown in the status bar.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    /// The connection dropped and the session is being resumed.
+    Resuming,
+    #[default]
+    Disconnected,
+    /// Discord refused the token, retrying won't help until it is changed.
+    AuthFailed,
+}
+
+impl ConnectionState {
+    pub fn label(self) -> &'static str {
+        match self {
+            ConnectionState::Connecting => "Connecting",
+            ConnectionState::Connected => "Connected",
+            ConnectionState::Resuming => "Resuming",
+            ConnectionState::Disconnected => "Disconnected",
+            ConnectionState::AuthFailed => "Token refused",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VoiceEventKind {
+    AlreadyIn,
+    Joined,
+    Left,
+    Moved,
+    Muted,
+    Unmuted,
+    Deafened,
+    Undeafened,
+    StartedStreaming,
+    StoppedStreaming,
+    CameraOn,
+    CameraOff,
+    ServerMuted,
+    ServerUnmuted,
+    ServerDeafened,
+    ServerUndeafened,
+    Suppressed,
+    Unsuppressed,
+}
+
+impl VoiceEventKind {
+    pub const ALL: [VoiceEventKind; 18] = [
+        VoiceEventKind::AlreadyIn,
+        VoiceEventKind::Joined,
+        VoiceEventKind::Left,
+        VoiceEventKind::Moved,
+        VoiceEventKind::Muted,
+        VoiceEventKind::Unmuted,
+        VoiceEventKind::Deafened,
+        VoiceEventKind::Undeafened,
+        VoiceEventKind::StartedStreaming,
+        VoiceEventKind::StoppedStreaming,
+        VoiceEventKind::CameraOn,
+        VoiceEventKind::CameraOff,
+        VoiceEventKind::ServerMuted,
+        VoiceEventKind::ServerUnmuted,
+        VoiceEventKind::ServerDeafened,
+        VoiceEventKind::ServerUndeafened,
+        VoiceEventKind::Suppressed,
+        VoiceEventKind::Unsuppressed,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            VoiceEventKind::AlreadyIn => "Already in",
+            VoiceEventKind::Joined => "Joined",
+            VoiceEventKind::Left => "Left",
+            VoiceEventKind::Moved => "Moved",
+            VoiceEventKind::Muted => "Muted",
+            VoiceEventKind::Unmuted => "Unmuted",
+            VoiceEventKind::Deafened => "Deafened",
+            VoiceEventKind::Undeafened => "Undeafened",
+            VoiceEventKind::StartedStreaming => "Went live",
+            VoiceEventKind::StoppedStreaming => "Stopped streaming",
+            VoiceEventKind::CameraOn => "Camera on",
+            VoiceEventKind::CameraOff => "Camera off",
+            VoiceEventKind::ServerMuted => "Server muted",
+            VoiceEventKind::ServerUnmuted => "Server unmuted",
+            VoiceEventKind::ServerDeafened => "Server deafened",
+            VoiceEventKind::ServerUndeafened => "Server undeafened",
+            VoiceEventKind::Suppressed => "Moved to audience",
+            VoiceEventKind::Unsuppressed => "Became speaker",
+        }
+    }
+
+    /// Stable snake case name, the same as the serialized form.
+    pub fn key(self) -> &'static str {
+        match self {
+            VoiceEventKind::AlreadyIn => "already_in",
+            VoiceEventKind::Joined => "joined",
+            VoiceEventKind::Left => "left",
+            VoiceEventKind::Moved => "moved",
+            VoiceEventKind::Muted => "muted",
+            VoiceEventKind::Unmuted => "unmuted",
+            VoiceEventKind::Deafened => "deafened",
+            VoiceEventKind::Undeafened => "undeafened",
+            VoiceEventKind::StartedStreaming => "started_streaming",
+            VoiceEventKind::StoppedStreaming => "stopped_streaming",
+            VoiceEventKind::CameraOn => "camera_on",
+            VoiceEventKind::CameraOff => "camera_off",
+            VoiceEventKind::ServerMuted => "server_muted",
+            VoiceEventKind::ServerUnmuted => "server_unmuted",
+            VoiceEventKind::ServerDeafened => "server_deafened",
+            VoiceEventKind::ServerUndeafened => "server_undeafened",
+            VoiceEventKind::Suppressed => "suppressed",
+            VoiceEventKind::Unsuppressed => "unsuppressed",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        VoiceEventKind::ALL
+            .into_iter()
+            .find(|kind| kind.key() == key)
+    }
+
+    pub fn category(self) -> EventCategory {
+        match self {
+            VoiceEventKind::AlreadyIn | VoiceEventKind::Joined | VoiceEventKind::Left => {
+                EventCategory::Presence
+            }
+            VoiceEventKind::Moved => EventCategory::Moves,
+            VoiceEventKind::Muted
+            | VoiceEventKind::Unmuted
+            | VoiceEventKind::Deafened
+            | VoiceEventKind::Undeafened => EventCategory::SelfMute,
+            VoiceEventKind::StartedStreaming | VoiceEventKind::StoppedStreaming => {
+                EventCategory::GoLive
+            }
+            VoiceEventKind::CameraOn | VoiceEventKind::CameraOff => EventCategory::Camera,
+            VoiceEventKind::ServerMuted
+            | VoiceEventKind::ServerUnmuted
+            | VoiceEventKind::ServerDeafened
+            | VoiceEventKind::ServerUndeafened
+            | VoiceEventKind::Suppressed
+            | VoiceEventKind::Unsuppressed => EventCategory::Moderation,
+        }
+    }
+
+    pub fn sound(self) -> SoundKind {
+        match self {
+            VoiceEventKind::AlreadyIn | VoiceEventKind::Joined => SoundKind::Join,
+            VoiceEventKind::Left => SoundKind::Leave,
+            VoiceEventKind::Moved => SoundKind::Move,
+            VoiceEventKind::StartedStreaming
+            | VoiceEventKind::StoppedStreaming
+            | VoiceEventKind::CameraOn
+            | VoiceEventKind::CameraOff => SoundKind::Stream,
+            VoiceEventKind::Muted
+            | VoiceEventKind::Unmuted
+            | VoiceEventKind::Deafened
+            | VoiceEventKind::Undeafened
+            | VoiceEventKind::ServerMuted
+            | VoiceEventKind::ServerUnmuted
+            | VoiceEventKind::ServerDeafened
+            | VoiceEventKind::ServerUndeafened
+            | VoiceEventKind::Suppressed
+            | VoiceEventKind::Unsuppressed => SoundKind::Mute,
+        }
+    }
+}
+
+/// Groups of [`VoiceEventKind`]s that are enabled or disabled together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventCategory {
+    Presence,
+    Moves,
+    SelfMute,
+    GoLive,
+    Camera,
+    Moderation,
+}
 
-    DebugData(CustomVoiceState, CustomVoiceState),
+impl EventCategory {
+    pub const ALL: [EventCategory; 6] = [
+        EventCategory::Presence,
+        EventCategory::Moves,
+        EventCategory::SelfMute,
+        EventCategory::GoLive,
+        EventCategory::Camera,
+        EventCategory::Moderation,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            EventCategory::Presence => "Join / Leave",
+            EventCategory::Moves => "Moves",
+            EventCategory::SelfMute => "Mute / Deafen",
+            EventCategory::GoLive => "Go Live",
+            EventCategory::Camera => "Camera",
+            EventCategory::Moderation => "Moderation",
+        }
+    }
+}
+
+/// Which sound of a sound pack an event plays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SoundKind {
+    Join,
+    Leave,
+    Move,
+    Mute,
+    Stream,
+}
+
+impl SoundKind {
+    pub const ALL: [SoundKind; 5] = [
+        SoundKind::Join,
+        SoundKind::Leave,
+        SoundKind::Move,
+        SoundKind::Mute,
+        SoundKind::Stream,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SoundKind::Join => "Join",
+            SoundKind::Leave => "Leave",
+            SoundKind::Move => "Move",
+            SoundKind::Mute => "Mute / Deafen",
+            SoundKind::Stream => "Go Live / Camera",
+        }
+    }
+
+    /// Name used in the sound pack config and as default file name.
+    pub fn key(self) -> &'static str {
+        match self {
+            SoundKind::Join => "join",
+            SoundKind::Leave => "leave",
+            SoundKind::Move => "move",
+            SoundKind::Mute => "mute",
+            SoundKind::Stream => "stream",
+        }
+    }
+}
+
+/// File format of an event history export, see [`crate::export`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    JsonLines,
+    Markdown,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 3] = [
+        ExportFormat::Csv,
+        ExportFormat::JsonLines,
+        ExportFormat::Markdown,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "CSV",
+            ExportFormat::JsonLines => "JSON Lines",
+            ExportFormat::Markdown => "Markdown table",
+        }
+    }
+
+    /// Name used by `--format` and as file extension.
+    pub fn key(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::JsonLines => "jsonl",
+            ExportFormat::Markdown => "md",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        ExportFormat::ALL
+            .into_iter()
+            .find(|format| format.key() == key)
+    }
+}
+
+/// An entry of the event list shown in the UI.
+#[derive(Debug, Clone)]
+pub enum LogEntry {
+    Voice(VoiceEvent),
+    Custom(String),
+    Error(String),
 }
 
 #[derive(Debug, Clone, Copy)]
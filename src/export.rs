@@ -0,0 +1,354 @@
+use std::{
+    error, fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local, NaiveDate, SecondsFormat, TimeDelta, Utc};
+use serde_json::json;
+
+use crate::{
+    enums::{ExportFormat, VoiceEventKind},
+    models::{VoiceEvent, WatchFilter},
+    sessions::midnight,
+    storage::{EventStore, StoredEvent},
+};
+
+/// Columns of the CSV and Markdown exports, also the keys of the JSON Lines
+/// objects.
+const COLUMNS: [&str; 14] = [
+    "id",
+    "timestamp",
+    "kind",
+    "guild_id",
+    "guild_name",
+    "user_id",
+    "user_name",
+    "channel_id",
+    "channel_name",
+    "from_channel_id",
+    "from_channel_name",
+    "moderator",
+    "resynced",
+    "description",
+];
+
+#[derive(Debug)]
+pub enum ExportError {
+    History(rusqlite::Error),
+    Write { path: PathBuf, source: io::Error },
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::History(e) => write!(f, "Error when reading event history: {}", e),
+            ExportError::Write { path, source } => {
+                write!(f, "Error when writing {}: {}", path.display(), source)
+            }
+        }
+    }
+}
+
+impl error::Error for ExportError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ExportError::History(e) => Some(e),
+            ExportError::Write { source, .. } => Some(source),
+        }
+    }
+}
+
+/// Which events of the history are exported.
+#[derive(Debug, Clone, Default)]
+pub struct ExportQuery {
+    /// First day exported, in local time.
+    pub from: Option<NaiveDate>,
+    /// Last day exported, included.
+    pub to: Option<NaiveDate>,
+    /// Guilds and event categories exported, all of them when `None`.
+    pub filter: Option<WatchFilter>,
+    /// Users exported, by ID or name ignoring case, all of them when empty.
+    pub users: Vec<String>,
+    /// Channels exported like `users`. A move matches the channel it comes
+    /// from too.
+    pub channels: Vec<String>,
+    /// Kinds of events exported, all of them when empty.
+    pub kinds: Vec<VoiceEventKind>,
+}
+
+impl ExportQuery {
+    fn range(&self) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+        let now = Local::now();
+        (
+            self.from.map(|date| midnight(&now, date)),
+            self.to
+                .map(|date| midnight(&now, date + TimeDelta::days(1))),
+        )
+    }
+
+    fn matches(&self, event: &VoiceEvent) -> bool {
+        let filter_matches = match self.filter {
+            Some(ref filter) => {
+                filter.is_guild_enabled(event.guild_id)
+                    && filter.is_category_enabled(event.kind.category())
+            }
+            None => true,
+        };
+        let channel_matches = self.channels.is_empty()
+            || is_named(&self.channels, event.channel_id.0, &event.channel_name)
+            || event.from_channel_id.is_some_and(|channel_id| {
+                let name = event.from_channel_name.as_deref().unwrap_or_default();
+                is_named(&self.channels, channel_id.0, name)
+            });
+
+        filter_matches
+            && (self.users.is_empty() || is_named(&self.users, event.user_id.0, &event.user_name))
+            && channel_matches
+            && (self.kinds.is_empty() || self.kinds.contains(&event.kind))
+    }
+}
+
+/// Whether one of `names` is `id` or `name`, ignoring case.
+fn is_named(names: &[String], id: u64, name: &str) -> bool {
+    let name = name.to_lowercase();
+    names
+        .iter()
+        .any(|wanted| wanted.trim() == id.to_string() || wanted.trim().to_lowercase() == name)
+}
+
+/// Parses a day given as `YYYY-MM-DD`.
+pub fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+        .map_err(|_| format!("{} is not a date, expected YYYY-MM-DD", value))
+}
+
+/// The format `path` asks for by its extension.
+pub fn format_of(path: &Path) -> Option<ExportFormat> {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(ExportFormat::from_key)
+}
+
+/// Exports from the event history of the user, for the `export` command.
+pub fn start_export(
+    query: &ExportQuery,
+    format: ExportFormat,
+    path: &Path,
+) -> Result<usize, ExportError> {
+    let store = EventStore::open().map_err(ExportError::History)?;
+    export_events(&store, query, format, path)
+}
+
+/// Writes the events of `store` matching `query` to `path`, returns how many
+/// were written.
+pub fn export_events(
+    store: &EventStore,
+    query: &ExportQuery,
+    format: ExportFormat,
+    path: &Path,
+) -> Result<usize, ExportError> {
+    let (from, to) = query.range();
+    let events = store
+        .between(from, to)
+        .map_err(ExportError::History)?
+        .into_iter()
+        .filter(|stored| query.matches(&stored.event))
+        .collect::<Vec<_>>();
+
+    let write = || -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        write_events(&mut writer, format, &events)?;
+        writer.flush()
+    };
+    write().map_err(|source| ExportError::Write {
+        path: path.to_owned(),
+        source,
+    })?;
+    Ok(events.len())
+}
+
+pub fn write_events(
+    writer: &mut impl Write,
+    format: ExportFormat,
+    events: &[StoredEvent],
+) -> io::Result<()> {
+    match format {
+        ExportFormat::Csv => {
+            writeln!(writer, "{}", COLUMNS.join(","))?;
+            for stored in events {
+                let row = row(stored).map(|field| csv_field(&field));
+                writeln!(writer, "{}", row.join(","))?;
+            }
+        }
+        ExportFormat::JsonLines => {
+            for stored in events {
+                let object = COLUMNS
+                    .iter()
+                    .zip(json_row(stored))
+                    .map(|(column, value)| (column.to_string(), value))
+                    .collect::<serde_json::Map<_, _>>();
+                writeln!(writer, "{}", serde_json::Value::Object(object))?;
+            }
+        }
+        ExportFormat::Markdown => {
+            writeln!(writer, "| {} |", COLUMNS.join(" | "))?;
+            writeln!(writer, "|{}", "---|".repeat(COLUMNS.len()))?;
+            for stored in events {
+                let row = row(stored).map(|field| markdown_field(&field));
+                writeln!(writer, "| {} |", row.join(" | "))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fields of `stored` in the order of [`COLUMNS`], empty when missing.
+fn row(stored: &StoredEvent) -> [String; 14] {
+    let event = &stored.event;
+    [
+        stored.id.to_string(),
+        event.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+        event.kind.key().to_owned(),
+        event.guild_id.to_string(),
+        event.guild_name.clone(),
+        event.user_id.to_string(),
+        event.user_name.clone(),
+        event.channel_id.to_string(),
+        event.channel_name.clone(),
+        event
+            .from_channel_id
+            .map(|id| id.to_string())
+            .unwrap_or_default(),
+        event.from_channel_name.clone().unwrap_or_default(),
+        event.moderator.clone().unwrap_or_default(),
+        event.resynced.to_string(),
+        event.description(),
+    ]
+}
+
+/// Same as [`row`] with `null` for missing fields. IDs stay strings, like in
+/// the Discord API, as they don't fit in a JavaScript number.
+fn json_row(stored: &StoredEvent) -> [serde_json::Value; 14] {
+    let event = &stored.event;
+    [
+        json!(stored.id),
+        json!(event.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true)),
+        json!(event.kind.key()),
+        json!(event.guild_id.to_string()),
+        json!(event.guild_name),
+        json!(event.user_id.to_string()),
+        json!(event.user_name),
+        json!(event.channel_id.to_string()),
+        json!(event.channel_name),
+        json!(event.from_channel_id.map(|id| id.to_string())),
+        json!(event.from_channel_name),
+        json!(event.moderator),
+        json!(event.resynced),
+        json!(event.description()),
+    ]
+}
+
+/// Quotes `field` when it holds a separator, a quote or a line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+fn markdown_field(field: &str) -> String {
+    field.replace('|', "\\|").replace(['\n', '\r'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{channel_name, voice_event, ALICE, BOB, GAMING, GENERAL};
+
+    fn stored(user_name: &str) -> StoredEvent {
+        StoredEvent {
+            id: 7,
+            event: VoiceEvent {
+                user_name: user_name.to_owned(),
+                from_channel_id: Some(GENERAL.into()),
+                from_channel_name: Some(channel_name(GENERAL).to_owned()),
+                ..voice_event(VoiceEventKind::Moved, ALICE, GAMING)
+            },
+        }
+    }
+
+    fn export(format: ExportFormat, user_name: &str) -> String {
+        let mut output = vec![];
+        write_events(&mut output, format, &[stored(user_name)]).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn writes_csv_with_quoted_fields() {
+        let output = export(ExportFormat::Csv, "smith, \"agent\"");
+        let lines = output.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines[0], COLUMNS.join(","));
+        assert_eq!(
+            lines[1],
+            "7,2024-05-15T20:00:00.000Z,moved,100,Test Guild,301,\"smith, \"\"agent\"\"\",\
+             202,Gaming,201,General,,false,\"smith, \"\"agent\"\" moved from General to Gaming\""
+        );
+    }
+
+    #[test]
+    fn writes_json_lines_with_string_ids() {
+        let output = export(ExportFormat::JsonLines, "alice");
+        let value = serde_json::from_str::<serde_json::Value>(output.trim()).unwrap();
+
+        assert_eq!(value["id"], 7);
+        assert_eq!(value["user_id"], "301");
+        assert_eq!(value["from_channel_id"], "201");
+        assert_eq!(value["moderator"], serde_json::Value::Null);
+        assert_eq!(value["timestamp"], "2024-05-15T20:00:00.000Z");
+    }
+
+    #[test]
+    fn escapes_markdown_pipes() {
+        let output = export(ExportFormat::Markdown, "a|b");
+        let lines = output.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1], format!("|{}", "---|".repeat(COLUMNS.len())));
+        assert!(lines[2].contains("| a\\|b |"));
+    }
+
+    #[test]
+    fn filters_users_channels_and_kinds() {
+        let moved = stored("alice").event;
+        let joined = voice_event(VoiceEventKind::Joined, BOB, GENERAL);
+        let query = |users: &[&str], channels: &[&str], kinds: &[VoiceEventKind]| ExportQuery {
+            users: users.iter().map(|user| user.to_string()).collect(),
+            channels: channels.iter().map(|channel| channel.to_string()).collect(),
+            kinds: kinds.to_vec(),
+            ..ExportQuery::default()
+        };
+
+        for (query, moved_matches, joined_matches) in [
+            (query(&[], &[], &[]), true, true),
+            (query(&["Alice"], &[], &[]), true, false),
+            (query(&[&BOB.to_string()], &[], &[]), false, true),
+            (query(&["alice", "bob"], &[], &[]), true, true),
+            (query(&[], &["gaming"], &[]), true, false),
+            // The move comes from General
+            (query(&[], &["General"], &[]), true, true),
+            (query(&[], &[], &[VoiceEventKind::Joined]), false, true),
+            (
+                query(&["alice"], &["General"], &[VoiceEventKind::Joined]),
+                false,
+                false,
+            ),
+        ] {
+            assert_eq!(query.matches(&moved), moved_matches, "{:?}", query);
+            assert_eq!(query.matches(&joined), joined_matches, "{:?}", query);
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! The guild, channels and members the tests play with.
+
+use chrono::{TimeZone, Utc};
+use serde_json::{json, Value};
+
+use crate::{
+    enums::VoiceEventKind,
+    models::{VoiceEvent, VoiceFlags},
+};
+
+pub const GUILD: u64 = 100;
+pub const GUILD_NAME: &str = "Test Guild";
+pub const GENERAL: u64 = 201;
+pub const GAMING: u64 = 202;
+pub const ALICE: u64 = 301;
+pub const BOB: u64 = 302;
+pub const CAROL: u64 = 303;
+/// The bot itself.
+pub const BOT: u64 = 399;
+
+pub fn user_name(user_id: u64) -> &'static str {
+    match user_id {
+        ALICE => "alice",
+        BOB => "bob",
+        CAROL => "carol",
+        _ => "someone",
+    }
+}
+
+pub fn channel_name(channel_id: u64) -> &'static str {
+    match channel_id {
+        GENERAL => "General",
+        GAMING => "Gaming",
+        _ => "Elsewhere",
+    }
+}
+
+/// Event of `user_id` in `channel_id` of the test guild, on Wednesday
+/// 2024-05-15 at 20:00 UTC, with every flag off.
+pub fn voice_event(kind: VoiceEventKind, user_id: u64, channel_id: u64) -> VoiceEvent {
+    VoiceEvent {
+        timestamp: Utc.with_ymd_and_hms(2024, 5, 15, 20, 0, 0).unwrap(),
+        kind,
+        guild_id: GUILD.into(),
+        guild_name: GUILD_NAME.to_owned(),
+        user_id: user_id.into(),
+        user_name: user_name(user_id).to_owned(),
+        roles: vec![],
+        channel_id: channel_id.into(),
+        channel_name: channel_name(channel_id).to_owned(),
+        from_channel_id: None,
+        from_channel_name: None,
+        before: VoiceFlags::default(),
+        after: VoiceFlags::default(),
+        moderator: None,
+        resynced: false,
+    }
+}
+
+/// Gateway JSON of the user `id`.
+pub fn user(id: u64, name: &str) -> Value {
+    json!({
+        "id": id.to_string(),
+        "username": name,
+        "discriminator": "0001",
+        "avatar": null,
+        "bot": id == BOT,
+    })
+}
+
+/// Gateway JSON of the member `id` of the test guild.
+pub fn member(id: u64, name: &str) -> Value {
+    json!({
+        "user": user(id, name),
+        "roles": [],
+        "joined_at": "2024-01-01T00:00:00.000000+00:00",
+        "deaf": false,
+        "mute": false,
+        "guild_id": GUILD.to_string(),
+    })
+}
+
+/// Gateway JSON of the voice state of `user_id` in `channel_id`, with every
+/// flag off.
+pub fn voice_state(user_id: u64, channel_id: Option<u64>) -> Value {
+    json!({
+        "guild_id": GUILD.to_string(),
+        "channel_id": channel_id.map(|id| id.to_string()),
+        "user_id": user_id.to_string(),
+        "member": member(user_id, user_name(user_id)),
+        "session_id": format!("session-{}", user_id),
+        "deaf": false,
+        "mute": false,
+        "self_deaf": false,
+        "self_mute": false,
+        "self_stream": false,
+        "self_video": false,
+        "suppress": false,
+        "request_to_speak_timestamp": null,
+    })
+}
+
+/// Sets the flags of a [`voice_state`] by their JSON name.
+pub fn with_flags(mut state: Value, flags: &[(&str, bool)]) -> Value {
+    for (flag, value) in flags {
+        state[*flag] = json!(value);
+    }
+    state
+}
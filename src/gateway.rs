@@ -0,0 +1,930 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serenity::{
+    async_trait,
+    model::{
+        event::Event,
+        guild::Guild,
+        id::{ChannelId, GuildId, RoleId, UserId},
+        prelude::Ready,
+        voice::VoiceState,
+    },
+    prelude::{Context, RawEventHandler},
+};
+use tokio::time;
+
+use crate::{
+    channel::{MessageSender, CAPACITY},
+    discord::{send_connection, DiscordApi, DiscordError, DiscordEventHandler},
+    enums::{ConnectionState, VoiceEventKind},
+};
+
+/// The voice related dispatches of the gateway, in the `t` and `d` fields of
+/// the gateway payload.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "t", content = "d", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum GatewayPayload {
+    Ready(Box<Ready>),
+    GuildCreate(Box<Guild>),
+    VoiceStateUpdate(VoiceState),
+}
+
+impl GatewayPayload {
+    /// The payload of `event`, `None` for events the handler doesn't use.
+    pub fn from_event(event: Event) -> Option<Self> {
+        match event {
+            Event::Ready(event) => Some(GatewayPayload::Ready(Box::new(event.ready))),
+            Event::GuildCreate(event) => Some(GatewayPayload::GuildCreate(Box::new(event.guild))),
+            Event::VoiceStateUpdate(event) => {
+                Some(GatewayPayload::VoiceStateUpdate(event.voice_state))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A line of a recording: a payload and when it was received.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedPayload {
+    pub time: DateTime<Utc>,
+    #[serde(flatten)]
+    pub payload: GatewayPayload,
+}
+
+/// Writes the payloads received by a client to a JSON Lines file, see
+/// [`RecordedPayload`].
+///
+/// Serenity only hands over the events it parsed, so the recording is a
+/// normalised copy of the traffic: the payloads are serialized back from
+/// the models, without the fields serenity doesn't know about and with the
+/// absent ones left out. Replaying it gives the handler the same events.
+pub struct Recorder {
+    path: PathBuf,
+    file: Mutex<File>,
+    /// Where a failed write is logged.
+    errors: MessageSender,
+}
+
+impl Recorder {
+    /// Opens `path`, appending to the recording when it exists.
+    pub fn open(path: &Path, errors: MessageSender) -> io::Result<Self> {
+        let file = File::options().create(true).append(true).open(path)?;
+        Ok(Recorder {
+            path: path.to_owned(),
+            file: Mutex::new(file),
+            errors,
+        })
+    }
+
+    pub fn record(&self, payload: GatewayPayload) -> io::Result<()> {
+        let mut value = serde_json::to_value(RecordedPayload {
+            time: Utc::now(),
+            payload,
+        })?;
+        // Serenity writes absent guild fields as `null` but can only read
+        // them back when they are missing, as Discord sends them
+        if let Some(data) = value.get_mut("d").and_then(Value::as_object_mut) {
+            data.retain(|_, field| !field.is_null());
+        }
+        let line = value.to_string();
+        // One write per line, a crash loses at most the payload being written
+        writeln!(self.file.lock().unwrap(), "{}", line)
+    }
+}
+
+#[async_trait]
+impl RawEventHandler for Recorder {
+    async fn raw_event(&self, _ctx: Context, event: Event) {
+        if let Some(payload) = GatewayPayload::from_event(event) {
+            if let Err(e) = self.record(payload) {
+                self.errors.log_error(format!(
+                    "Error when recording payload to {}: {}",
+                    self.path.display(),
+                    e
+                ));
+            }
+        }
+    }
+}
+
+/// Options of the `replay` command.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub path: PathBuf,
+    /// Feed the payloads without waiting between them.
+    pub fast: bool,
+}
+
+/// Stands in for the cache and the HTTP API, answering from the guilds and
+/// members seen in the payloads.
+#[derive(Debug, Default)]
+pub struct LocalApi {
+    guilds: RwLock<HashMap<GuildId, Guild>>,
+}
+
+impl LocalApi {
+    pub fn add_guild(&self, guild: &Guild) {
+        self.guilds.write().unwrap().insert(guild.id, guild.clone());
+    }
+
+    /// Remembers the member of `state`, for members who joined after the
+    /// guild was created.
+    pub fn add_member(&self, state: &VoiceState) {
+        let (guild_id, member) = match (state.guild_id, &state.member) {
+            (Some(guild_id), Some(member)) => (guild_id, member),
+            _ => return,
+        };
+        if let Some(guild) = self.guilds.write().unwrap().get_mut(&guild_id) {
+            guild.members.insert(member.user.id, member.clone());
+        }
+    }
+}
+
+#[async_trait]
+impl DiscordApi for LocalApi {
+    async fn guild_name(&self, guild_id: GuildId) -> String {
+        match self.guilds.read().unwrap().get(&guild_id) {
+            Some(guild) => guild.name.clone(),
+            None => String::default(),
+        }
+    }
+
+    async fn channel_name(&self, channel_id: ChannelId) -> Result<String, DiscordError> {
+        self.guilds
+            .read()
+            .unwrap()
+            .values()
+            .find_map(|guild| guild.channels.get(&channel_id))
+            .and_then(|channel| channel.clone().guild())
+            .map(|channel| channel.name)
+            .ok_or(DiscordError::NotGuildChannel(channel_id))
+    }
+
+    async fn user_name(&self, user_id: UserId) -> String {
+        self.guilds
+            .read()
+            .unwrap()
+            .values()
+            .find_map(|guild| guild.members.get(&user_id))
+            .map(|member| member.user.name.clone())
+            .unwrap_or_default()
+    }
+
+    async fn member_roles(&self, guild_id: GuildId, user_id: UserId) -> Vec<RoleId> {
+        self.guilds
+            .read()
+            .unwrap()
+            .get(&guild_id)
+            .and_then(|guild| guild.members.get(&user_id))
+            .map(|member| member.roles.clone())
+            .unwrap_or_default()
+    }
+
+    /// There is no audit log to read.
+    async fn moderator_name(
+        &self,
+        _guild_id: GuildId,
+        _user_id: UserId,
+        _kind: VoiceEventKind,
+    ) -> Option<String> {
+        None
+    }
+}
+
+/// Stands in for the gateway, feeding payloads to a handler in order without
+/// connecting to Discord.
+pub struct LocalGateway {
+    pub handler: DiscordEventHandler,
+    pub api: LocalApi,
+}
+
+impl LocalGateway {
+    pub fn new(handler: DiscordEventHandler) -> Self {
+        LocalGateway {
+            handler,
+            api: LocalApi::default(),
+        }
+    }
+
+    /// Feeds `payload` as if it was received at `time`.
+    pub async fn feed(&self, payload: GatewayPayload, time: DateTime<Utc>) {
+        match payload {
+            GatewayPayload::Ready(ready) => self.handler.on_ready(*ready).await,
+            GatewayPayload::GuildCreate(guild) => {
+                self.api.add_guild(&guild);
+                self.handler.on_guild_create(&self.api, *guild, time).await;
+            }
+            GatewayPayload::VoiceStateUpdate(state) => {
+                self.api.add_member(&state);
+                // Like serenity without a cache, the handler keeps the
+                // previous states itself
+                self.handler
+                    .on_voice_state_update(&self.api, None, state, time)
+                    .await;
+            }
+        }
+    }
+
+    /// Feeds the payloads of a recording, waiting between them as long as
+    /// when they were recorded unless `replay.fast`. The events keep the
+    /// recorded time. Returns the number of payloads fed, invalid lines are
+    /// skipped.
+    pub async fn replay(&self, replay: &Replay) -> Result<usize, DiscordError> {
+        let content = fs::read_to_string(&replay.path)
+            .map_err(DiscordError::recording("reading", &replay.path))?;
+
+        send_connection(&self.handler.tx, ConnectionState::Connected);
+        let mut previous_time: Option<DateTime<Utc>> = None;
+        let mut count = 0;
+        for (idx, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let recorded = match serde_json::from_str::<RecordedPayload>(line) {
+                Ok(recorded) => recorded,
+                Err(e) => {
+                    eprintln!(
+                        "Error when reading line {} of {}: {}",
+                        idx + 1,
+                        replay.path.display(),
+                        e
+                    );
+                    continue;
+                }
+            };
+
+            if let (Some(previous_time), false) = (previous_time, replay.fast) {
+                if let Ok(delay) = (recorded.time - previous_time).to_std() {
+                    time::sleep(delay).await;
+                }
+            }
+            previous_time = Some(recorded.time);
+            // Lets the UI catch up instead of dropping messages, a payload
+            // rarely sends more than half the queue
+            self.handler.tx.wait_for_room(CAPACITY / 2).await;
+
+            self.feed(recorded.payload, recorded.time).await;
+            count += 1;
+        }
+        send_connection(&self.handler.tx, ConnectionState::Disconnected);
+
+        Ok(count)
+    }
+}
+
+/// Replays a recording through `handler` on the tokio runtime, as if the
+/// payloads came from Discord.
+pub fn spawn_replay(handler: DiscordEventHandler, replay: Replay) {
+    tokio::spawn(async move {
+        let gateway = LocalGateway::new(handler);
+        match gateway.replay(&replay).await {
+            Ok(count) => println!("Replayed {} payloads", count),
+            Err(e) => gateway.handler.report(e),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, process,
+        sync::{Arc, Mutex},
+    };
+
+    use chrono::TimeDelta;
+    use serde_json::json;
+    use serenity::prelude::GatewayIntents;
+
+    use super::*;
+    use crate::{
+        channel::{message_channel, MessageReceiver},
+        discord::{intents, AlertSink, DebugUpdate},
+        enums::{ChannelMessage, EventCategory, SoundKind},
+        fixtures::{
+            channel_name, voice_state, with_flags, ALICE, BOB, BOT, CAROL, GAMING, GENERAL, GUILD,
+            GUILD_NAME,
+        },
+        models::{VoiceEvent, WatchFilter},
+        storage::EventStore,
+    };
+
+    /// Keeps the toasts and sounds instead of playing them.
+    #[derive(Default)]
+    struct RecordedAlerts {
+        toasts: Mutex<Vec<String>>,
+        sounds: Mutex<Vec<SoundKind>>,
+    }
+
+    impl AlertSink for RecordedAlerts {
+        fn toast(&self, body: &str) {
+            self.toasts.lock().unwrap().push(body.to_owned());
+        }
+
+        fn sound(&self, kind: SoundKind) {
+            self.sounds.lock().unwrap().push(kind);
+        }
+    }
+
+    struct Harness {
+        gateway: LocalGateway,
+        rx: MessageReceiver,
+        alerts: Arc<RecordedAlerts>,
+        watch_filter: Arc<RwLock<WatchFilter>>,
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            Self::with_store(None)
+        }
+
+        fn with_store(store: Option<EventStore>) -> Self {
+            let (tx, rx) = message_channel(None);
+            let alerts = Arc::new(RecordedAlerts::default());
+            let watch_filter = Arc::default();
+            let handler = DiscordEventHandler::with_sinks(
+                tx,
+                Arc::clone(&watch_filter),
+                Arc::default(),
+                alerts.clone(),
+                store,
+            );
+            Harness {
+                gateway: LocalGateway::new(handler),
+                rx,
+                alerts,
+                watch_filter,
+            }
+        }
+
+        async fn feed(&self, payload: Value) {
+            let payload = serde_json::from_value::<GatewayPayload>(payload).unwrap();
+            self.gateway.feed(payload, Utc::now()).await;
+        }
+
+        /// Connects with `voice_states` already in the guild, clearing what
+        /// the startup reported.
+        async fn start(&mut self, voice_states: Vec<Value>) {
+            self.feed(ready()).await;
+            self.feed(guild_create(voice_states)).await;
+            self.voice_events();
+            self.toasts();
+        }
+
+        /// Voice events sent to the UI since the last call, as
+        /// `(kind, user, channel)`.
+        fn voice_events(&mut self) -> Vec<(VoiceEventKind, String, String)> {
+            self.events()
+                .into_iter()
+                .map(|event| (event.kind, event.user_name, event.channel_name))
+                .collect()
+        }
+
+        /// Debug inspector updates sent since the last call, dropping the
+        /// other messages.
+        fn debug_updates(&mut self) -> Vec<DebugUpdate> {
+            let mut updates = vec![];
+            while let Ok(message) = self.rx.try_recv() {
+                if let ChannelMessage::DebugData(update) = message {
+                    updates.push(*update);
+                }
+            }
+            updates
+        }
+
+        fn events(&mut self) -> Vec<VoiceEvent> {
+            let mut events = vec![];
+            while let Ok(message) = self.rx.try_recv() {
+                match message {
+                    ChannelMessage::Voice(event) => events.push(event),
+                    ChannelMessage::Error(error) => panic!("handler error: {}", error),
+                    _ => (),
+                }
+            }
+            events
+        }
+
+        fn toasts(&self) -> Vec<String> {
+            self.alerts.toasts.lock().unwrap().drain(..).collect()
+        }
+
+        fn sounds(&self) -> Vec<SoundKind> {
+            self.alerts.sounds.lock().unwrap().drain(..).collect()
+        }
+    }
+
+    fn voice_state_update(state: Value) -> Value {
+        json!({ "t": "VOICE_STATE_UPDATE", "d": state })
+    }
+
+    fn ready() -> Value {
+        json!({
+            "t": "READY",
+            "d": {
+                "v": 10,
+                "user": {
+                    "id": BOT.to_string(),
+                    "username": "watcher",
+                    "discriminator": "0001",
+                    "avatar": null,
+                    "bot": true,
+                    "verified": true,
+                    "mfa_enabled": false,
+                },
+                "guilds": [{ "id": GUILD.to_string(), "unavailable": true }],
+                "session_id": "session",
+                "application": { "id": BOT.to_string(), "flags": 0 },
+                "private_channels": [],
+                "presences": [],
+                "shard": [0, 1],
+            },
+        })
+    }
+
+    fn voice_channel(id: u64) -> Value {
+        json!({
+            "id": id.to_string(),
+            "type": 2,
+            "guild_id": GUILD.to_string(),
+            "name": channel_name(id),
+            "position": 0,
+            "permission_overwrites": [],
+            "bitrate": 64000,
+            "user_limit": 0,
+            "nsfw": false,
+        })
+    }
+
+    fn guild_create(voice_states: Vec<Value>) -> Value {
+        let members = voice_states
+            .iter()
+            .map(|state| state["member"].clone())
+            .collect::<Vec<_>>();
+        json!({
+            "t": "GUILD_CREATE",
+            "d": {
+                "id": GUILD.to_string(),
+                "name": GUILD_NAME,
+                "owner_id": ALICE.to_string(),
+                "afk_timeout": 300,
+                "channels": [voice_channel(GENERAL), voice_channel(GAMING)],
+                "default_message_notifications": 0,
+                "emojis": [],
+                "explicit_content_filter": 0,
+                "features": [],
+                "joined_at": "2024-01-01T00:00:00.000000+00:00",
+                "large": false,
+                "member_count": members.len(),
+                "members": members,
+                "mfa_level": 0,
+                "presences": [],
+                "roles": [],
+                "verification_level": 0,
+                "voice_states": voice_states,
+                "preferred_locale": "en-US",
+                "nsfw_level": 0,
+                "system_channel_flags": 0,
+                "stickers": [],
+            },
+        })
+    }
+
+    fn change(kind: VoiceEventKind, user: &str, channel: &str) -> (VoiceEventKind, String, String) {
+        (kind, user.to_owned(), channel.to_owned())
+    }
+
+    #[test]
+    fn requests_the_intents_of_the_replayed_events() {
+        let intents = intents();
+        for (payload, intent) in [
+            (guild_create(vec![]), GatewayIntents::GUILDS),
+            (
+                voice_state_update(voice_state(ALICE, Some(GENERAL))),
+                GatewayIntents::GUILD_VOICE_STATES,
+            ),
+        ] {
+            assert!(
+                intents.contains(intent),
+                "{} needs {:?}",
+                payload["t"],
+                intent
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn startup_reports_members_with_one_toast() {
+        let mut harness = Harness::new();
+        harness.feed(ready()).await;
+        harness
+            .feed(guild_create(vec![
+                voice_state(ALICE, Some(GENERAL)),
+                voice_state(BOB, Some(GAMING)),
+            ]))
+            .await;
+
+        let mut events = harness.voice_events();
+        events.sort_by(|a, b| a.1.cmp(&b.1));
+        assert_eq!(
+            events,
+            vec![
+                change(VoiceEventKind::AlreadyIn, "alice", "General"),
+                change(VoiceEventKind::AlreadyIn, "bob", "Gaming"),
+            ]
+        );
+        assert_eq!(
+            harness.toasts(),
+            vec!["Watching 1 guild, 2 members in voice".to_owned()]
+        );
+        assert!(harness.sounds().is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_join_move_and_leave() {
+        let mut harness = Harness::new();
+        harness.start(vec![]).await;
+
+        harness
+            .feed(voice_state_update(voice_state(ALICE, Some(GENERAL))))
+            .await;
+        harness
+            .feed(voice_state_update(voice_state(ALICE, Some(GAMING))))
+            .await;
+        harness
+            .feed(voice_state_update(voice_state(ALICE, None)))
+            .await;
+
+        let events = harness.events();
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| event.description())
+                .collect::<Vec<_>>(),
+            vec![
+                "alice joined General",
+                "alice moved from General to Gaming",
+                "alice left Gaming",
+            ]
+        );
+        assert_eq!(events[1].from_channel_id, Some(GENERAL.into()));
+        assert_eq!(
+            harness.toasts(),
+            vec![
+                "alice joined General (Test Guild)",
+                "alice moved from General to Gaming (Test Guild)",
+                "alice left Gaming (Test Guild)",
+            ]
+        );
+        assert_eq!(
+            harness.sounds(),
+            vec![SoundKind::Join, SoundKind::Move, SoundKind::Leave]
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_mute_and_deafen() {
+        let mut harness = Harness::new();
+        harness.start(vec![voice_state(ALICE, Some(GENERAL))]).await;
+
+        let state = voice_state(ALICE, Some(GENERAL));
+        for flags in [
+            &[("self_mute", true)][..],
+            &[("self_mute", true), ("self_deaf", true)],
+            &[],
+        ] {
+            harness
+                .feed(voice_state_update(with_flags(state.clone(), flags)))
+                .await;
+        }
+
+        assert_eq!(
+            harness.voice_events(),
+            vec![
+                change(VoiceEventKind::Muted, "alice", "General"),
+                change(VoiceEventKind::Deafened, "alice", "General"),
+                change(VoiceEventKind::Unmuted, "alice", "General"),
+                change(VoiceEventKind::Undeafened, "alice", "General"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn sends_changed_fields_to_the_inspector() {
+        let mut harness = Harness::new();
+        harness.start(vec![voice_state(ALICE, Some(GENERAL))]).await;
+
+        let state = voice_state(ALICE, Some(GAMING));
+        harness
+            .feed(voice_state_update(with_flags(
+                state,
+                &[("self_mute", true)],
+            )))
+            .await;
+
+        let updates = harness.debug_updates();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].changed_fields(), vec!["channel", "self_mute"]);
+        assert_eq!(updates[0].new_state.username, "alice");
+        assert!(updates[0].matches("GAMING"));
+        assert!(!updates[0].matches("bob"));
+        assert!(updates[0].payload.contains("\"VOICE_STATE_UPDATE\""));
+        assert!(updates[0].report().contains("* self_mute: false -> true"));
+    }
+
+    #[tokio::test]
+    async fn tells_server_mutes_apart() {
+        let mut harness = Harness::new();
+        harness.start(vec![voice_state(ALICE, Some(GENERAL))]).await;
+
+        let state = voice_state(ALICE, Some(GENERAL));
+        harness
+            .feed(voice_state_update(with_flags(
+                state.clone(),
+                &[("mute", true), ("deaf", true)],
+            )))
+            .await;
+        harness.feed(voice_state_update(state)).await;
+
+        let events = harness.events();
+        assert_eq!(
+            events.iter().map(|event| event.kind).collect::<Vec<_>>(),
+            vec![
+                VoiceEventKind::ServerMuted,
+                VoiceEventKind::ServerDeafened,
+                VoiceEventKind::ServerUnmuted,
+                VoiceEventKind::ServerUndeafened,
+            ]
+        );
+        assert!(events.iter().all(|event| event.moderator.is_none()));
+    }
+
+    #[tokio::test]
+    async fn reports_stream_and_camera() {
+        let mut harness = Harness::new();
+        harness.start(vec![voice_state(ALICE, Some(GENERAL))]).await;
+
+        let state = voice_state(ALICE, Some(GENERAL));
+        harness
+            .feed(voice_state_update(with_flags(
+                state.clone(),
+                &[("self_stream", true)],
+            )))
+            .await;
+        harness
+            .feed(voice_state_update(with_flags(
+                state,
+                &[("self_stream", true), ("self_video", true)],
+            )))
+            .await;
+
+        assert_eq!(
+            harness.voice_events(),
+            vec![
+                change(VoiceEventKind::StartedStreaming, "alice", "General"),
+                change(VoiceEventKind::CameraOn, "alice", "General"),
+            ]
+        );
+        assert_eq!(harness.sounds(), vec![SoundKind::Stream, SoundKind::Stream]);
+    }
+
+    /// Updates of one member are never compared with another member's state.
+    #[tokio::test]
+    async fn compares_each_member_with_their_own_state() {
+        let mut harness = Harness::new();
+        harness
+            .start(vec![
+                voice_state(ALICE, Some(GENERAL)),
+                voice_state(BOB, Some(GAMING)),
+            ])
+            .await;
+
+        harness
+            .feed(voice_state_update(with_flags(
+                voice_state(ALICE, Some(GENERAL)),
+                &[("self_mute", true)],
+            )))
+            .await;
+        // Nothing changed for bob
+        harness
+            .feed(voice_state_update(voice_state(BOB, Some(GAMING))))
+            .await;
+        harness
+            .feed(voice_state_update(voice_state(BOB, Some(GENERAL))))
+            .await;
+
+        let events = harness.events();
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| event.description())
+                .collect::<Vec<_>>(),
+            vec![
+                "alice muted themselves in General",
+                "bob moved from Gaming to General"
+            ]
+        );
+        assert_eq!(events[1].before, events[1].after);
+    }
+
+    #[tokio::test]
+    async fn resync_reports_only_missed_changes() {
+        let mut harness = Harness::new();
+        harness
+            .start(vec![
+                voice_state(ALICE, Some(GENERAL)),
+                voice_state(BOB, Some(GAMING)),
+            ])
+            .await;
+
+        // Reconnect: alice moved, bob left and carol joined in between
+        harness.feed(ready()).await;
+        harness
+            .feed(guild_create(vec![
+                with_flags(voice_state(ALICE, Some(GAMING)), &[("self_mute", true)]),
+                voice_state(CAROL, Some(GENERAL)),
+            ]))
+            .await;
+
+        let events = harness.events();
+        assert!(events.iter().all(|event| event.resynced));
+        let mut changes = events
+            .iter()
+            .map(|event| event.description())
+            .collect::<Vec<_>>();
+        changes.sort();
+        assert_eq!(
+            changes,
+            vec![
+                "alice moved from General to Gaming (detected on resync)",
+                "bob left Gaming (detected on resync)",
+                "carol joined General (detected on resync)",
+            ]
+        );
+        assert_eq!(
+            harness.toasts(),
+            vec!["Reconnected, 3 voice changes while disconnected".to_owned()]
+        );
+        assert!(harness.sounds().is_empty());
+
+        // Nothing is reported when nothing changed
+        harness.feed(ready()).await;
+        harness
+            .feed(guild_create(vec![
+                with_flags(voice_state(ALICE, Some(GAMING)), &[("self_mute", true)]),
+                voice_state(CAROL, Some(GENERAL)),
+            ]))
+            .await;
+        assert!(harness.voice_events().is_empty());
+        assert!(harness.toasts().is_empty());
+    }
+
+    #[tokio::test]
+    async fn ignores_disabled_guilds() {
+        let mut harness = Harness::new();
+        harness
+            .watch_filter
+            .write()
+            .unwrap()
+            .set_guild_enabled(GUILD.into(), false);
+        harness.start(vec![voice_state(ALICE, Some(GENERAL))]).await;
+
+        harness
+            .feed(voice_state_update(voice_state(ALICE, None)))
+            .await;
+
+        assert!(harness.voice_events().is_empty());
+        assert!(harness.sounds().is_empty());
+    }
+
+    #[tokio::test]
+    async fn keeps_the_roster_of_hidden_events() {
+        let mut harness = Harness::new();
+        harness.start(vec![voice_state(ALICE, Some(GENERAL))]).await;
+        for category in [EventCategory::Presence, EventCategory::Moves] {
+            harness
+                .watch_filter
+                .write()
+                .unwrap()
+                .set_category_enabled(category, false);
+        }
+
+        harness
+            .feed(voice_state_update(voice_state(ALICE, Some(GAMING))))
+            .await;
+        harness
+            .feed(voice_state_update(voice_state(BOB, Some(GENERAL))))
+            .await;
+
+        assert!(harness.voice_events().is_empty());
+        let roster = harness.gateway.handler.roster.read().unwrap();
+        let channels = roster.guilds[0]
+            .channels
+            .iter()
+            .map(|channel| {
+                let members = channel
+                    .members
+                    .iter()
+                    .map(|member| member.user_name.as_str());
+                (channel.channel_name.as_str(), members.collect::<Vec<_>>())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            channels,
+            vec![("Gaming", vec!["alice"]), ("General", vec!["bob"])]
+        );
+    }
+
+    #[tokio::test]
+    async fn saves_hidden_categories_to_the_history() {
+        let path = env::temp_dir().join(format!("discord_watcher_{}.db", process::id()));
+        let mut harness = Harness::with_store(Some(EventStore::open_at(path.clone()).unwrap()));
+        harness
+            .watch_filter
+            .write()
+            .unwrap()
+            .set_category_enabled(EventCategory::Presence, false);
+        harness.start(vec![voice_state(ALICE, Some(GENERAL))]).await;
+
+        harness
+            .feed(voice_state_update(voice_state(BOB, Some(GAMING))))
+            .await;
+        assert!(harness.voice_events().is_empty());
+
+        let history = EventStore::open_at(path.clone())
+            .unwrap()
+            .latest(10)
+            .unwrap();
+        drop(harness);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+        let kinds = history
+            .into_iter()
+            .map(|stored| (stored.event.kind, stored.event.user_name))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                (VoiceEventKind::Joined, "bob".to_owned()),
+                (VoiceEventKind::AlreadyIn, "alice".to_owned()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn replays_a_recording() {
+        let path = env::temp_dir().join(format!("discord_watcher_{}.jsonl", process::id()));
+        let recorder = Recorder::open(&path, message_channel(None).0).unwrap();
+        for payload in [
+            ready(),
+            guild_create(vec![voice_state(ALICE, Some(GENERAL))]),
+            voice_state_update(voice_state(BOB, Some(GAMING))),
+        ] {
+            let payload = serde_json::from_value::<GatewayPayload>(payload).unwrap();
+            recorder.record(payload).unwrap();
+        }
+        drop(recorder);
+        // Recorded a minute apart, a day ago
+        let start = Utc::now() - TimeDelta::days(1);
+        let mut content = String::new();
+        for (idx, line) in fs::read_to_string(&path).unwrap().lines().enumerate() {
+            let mut value = serde_json::from_str::<Value>(line).unwrap();
+            value["time"] = json!(start + TimeDelta::minutes(idx as i64));
+            content += &format!("{}\n", value);
+        }
+        fs::write(&path, content + "not a payload\n").unwrap();
+
+        let mut harness = Harness::new();
+        let replay = Replay {
+            path: path.clone(),
+            fast: true,
+        };
+        let count = harness.gateway.replay(&replay).await;
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(count.unwrap(), 3);
+        let events = harness.events();
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| (event.kind, event.user_name.as_str(), event.timestamp))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    VoiceEventKind::AlreadyIn,
+                    "alice",
+                    start + TimeDelta::minutes(1)
+                ),
+                (VoiceEventKind::Joined, "bob", start + TimeDelta::minutes(2)),
+            ]
+        );
+    }
+}
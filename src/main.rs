@@ -1,33 +1,104 @@
 #![warn(clippy::all)]
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::env;
+use std::{env, process};
 
+use cli::{Cli, Command, USAGE};
+use daemon::{start_daemon, start_replay};
 use egui::egui_main::start_egui;
+use export::{format_of, start_export, ExportQuery};
+use gateway::Replay;
+use tui::tui_main::start_tui;
 
 pub mod egui {
     pub mod app;
+    pub mod debug_inspector;
     pub mod egui_main;
+    pub mod export_window;
+    pub mod rules_window;
+    pub mod settings_window;
+    pub mod sounds_window;
+    pub mod statistics_window;
+    pub mod token_window;
     pub mod top_bar;
 }
+pub mod tui {
+    pub mod app;
+    pub mod tui_main;
+}
+mod audio;
+mod channel;
+mod cli;
+mod config;
+mod daemon;
 mod discord;
 mod enums;
+mod export;
+#[cfg(test)]
+mod fixtures;
+mod gateway;
 mod models;
+mod rules;
+mod sessions;
+mod storage;
+mod token;
 mod utils;
 
 #[cfg(not(target_arch = "wasm32"))]
 fn main() {
-    let args = env::args().nth(1);
+    let cli = match Cli::parse(env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    let config = cli.load_config();
 
-    match args.as_deref() {
-        Some("egui") => {
-            start_egui().expect("Failed to start egui");
+    match cli.command {
+        Command::Egui => {
+            start_egui(config, None).expect("Failed to start egui");
+        }
+        Command::Tui => {
+            start_tui(config).expect("Failed to start tui");
+        }
+        Command::Daemon => {
+            start_daemon(config);
+        }
+        Command::Replay(path) => {
+            let replay = Replay {
+                path,
+                fast: cli.fast,
+            };
+            if cli.headless {
+                start_replay(config, replay);
+            } else {
+                start_egui(config, Some(replay)).expect("Failed to start egui");
+            }
+        }
+        Command::Export(path) => {
+            let query = ExportQuery {
+                from: cli.from,
+                to: cli.to,
+                filter: (!cli.all).then_some(config.filter),
+                users: cli.users,
+                channels: cli.channels,
+                kinds: cli.kinds,
+            };
+            let format = cli.format.or_else(|| format_of(&path)).unwrap_or_default();
+            match start_export(&query, format, &path) {
+                Ok(count) => println!("Exported {} events to {}", count, path.display()),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
         }
-        Some("gtk") => {
+        Command::Gtk => {
             eprintln!("GTK is not supported yet. Please use `egui` as an argument.");
         }
-        Some(_) | None => {
-            start_egui().expect("Failed to start egui");
+        Command::Help => {
+            println!("{}", USAGE);
         }
     }
 }
@@ -0,0 +1,365 @@
+use std::{collections::HashSet, fmt};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use serenity::model::{
+    id::{ChannelId, GuildId, RoleId, UserId},
+    voice::VoiceState,
+};
+
+use crate::enums::{EventCategory, VoiceEventKind};
+
+/// Which guilds and event categories the watcher reports events for.
+///
+/// Shared between the Discord handler and the UI, everything is watched
+/// until the user disables it from the `Guilds` or `Events` menu.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WatchFilter {
+    disabled_guilds: HashSet<GuildId>,
+    disabled_categories: HashSet<EventCategory>,
+}
+
+impl WatchFilter {
+    pub fn is_guild_enabled(&self, guild_id: GuildId) -> bool {
+        !self.disabled_guilds.contains(&guild_id)
+    }
+
+    pub fn set_guild_enabled(&mut self, guild_id: GuildId, enabled: bool) {
+        if enabled {
+            self.disabled_guilds.remove(&guild_id);
+        } else {
+            self.disabled_guilds.insert(guild_id);
+        }
+    }
+
+    pub fn is_category_enabled(&self, category: EventCategory) -> bool {
+        !self.disabled_categories.contains(&category)
+    }
+
+    pub fn set_category_enabled(&mut self, category: EventCategory, enabled: bool) {
+        if enabled {
+            self.disabled_categories.remove(&category);
+        } else {
+            self.disabled_categories.insert(category);
+        }
+    }
+}
+
+/// Voice flags of a member at one point in time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VoiceFlags {
+    pub self_mute: bool,
+    pub self_deaf: bool,
+    pub self_stream: bool,
+    pub self_video: bool,
+    pub mute: bool,
+    pub deaf: bool,
+    pub suppress: bool,
+}
+
+impl VoiceFlags {
+    /// Packs the flags into an integer, one bit per flag in declaration order.
+    pub fn bits(self) -> u8 {
+        [
+            self.self_mute,
+            self.self_deaf,
+            self.self_stream,
+            self.self_video,
+            self.mute,
+            self.deaf,
+            self.suppress,
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |bits, (idx, flag)| bits | (u8::from(*flag) << idx))
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        let flag = |idx: u8| bits & (1 << idx) != 0;
+        VoiceFlags {
+            self_mute: flag(0),
+            self_deaf: flag(1),
+            self_stream: flag(2),
+            self_video: flag(3),
+            mute: flag(4),
+            deaf: flag(5),
+            suppress: flag(6),
+        }
+    }
+}
+
+impl From<&VoiceState> for VoiceFlags {
+    fn from(state: &VoiceState) -> Self {
+        VoiceFlags {
+            self_mute: state.self_mute,
+            self_deaf: state.self_deaf,
+            self_stream: state.self_stream.unwrap_or(false),
+            self_video: state.self_video,
+            mute: state.mute,
+            deaf: state.deaf,
+            suppress: state.suppress,
+        }
+    }
+}
+
+/// A single change in a guild's voice channels, as reported to the UI and
+/// the notifications.
+#[derive(Debug, Clone)]
+pub struct VoiceEvent {
+    pub timestamp: DateTime<Utc>,
+    pub kind: VoiceEventKind,
+
+    pub guild_id: GuildId,
+    pub guild_name: String,
+
+    pub user_id: UserId,
+    pub user_name: String,
+    /// Roles of the user in the guild, used by the notification rules.
+    pub roles: Vec<RoleId>,
+
+    /// Channel the event happened in, the channel that was left for
+    /// [`VoiceEventKind::Left`] and the destination for [`VoiceEventKind::Moved`].
+    pub channel_id: ChannelId,
+    pub channel_name: String,
+    /// Channel the user came from, only set for [`VoiceEventKind::Moved`].
+    pub from_channel_id: Option<ChannelId>,
+    pub from_channel_name: Option<String>,
+
+    pub before: VoiceFlags,
+    pub after: VoiceFlags,
+
+    /// Who applied a server mute or deafen, when the audit log could be read.
+    pub moderator: Option<String>,
+    /// The change happened while the bot was disconnected and was found by
+    /// comparing the voice states after reconnecting.
+    pub resynced: bool,
+}
+
+impl VoiceEvent {
+    /// Human readable description without the guild, e.g. `"foo joined General"`.
+    pub fn description(&self) -> String {
+        if self.resynced {
+            return format!("{} (detected on resync)", self.change());
+        }
+        self.change()
+    }
+
+    fn change(&self) -> String {
+        let user = &self.user_name;
+        let channel = &self.channel_name;
+        match self.kind {
+            VoiceEventKind::AlreadyIn => format!("{} is already in {}", user, channel),
+            VoiceEventKind::Joined => format!("{} joined {}", user, channel),
+            VoiceEventKind::Left => format!("{} left {}", user, channel),
+            VoiceEventKind::Moved => format!(
+                "{} moved from {} to {}",
+                user,
+                self.from_channel_name.as_deref().unwrap_or_default(),
+                channel
+            ),
+            VoiceEventKind::Muted => format!("{} muted themselves in {}", user, channel),
+            VoiceEventKind::Unmuted => format!("{} unmuted themselves in {}", user, channel),
+            VoiceEventKind::Deafened => format!("{} deafened themselves in {}", user, channel),
+            VoiceEventKind::Undeafened => {
+                format!("{} undeafened themselves in {}", user, channel)
+            }
+            VoiceEventKind::StartedStreaming => {
+                format!("{} went live in {}", user, channel)
+            }
+            VoiceEventKind::StoppedStreaming => {
+                format!("{} stopped streaming in {}", user, channel)
+            }
+            VoiceEventKind::CameraOn => format!("{} turned their camera on in {}", user, channel),
+            VoiceEventKind::CameraOff => format!("{} turned their camera off in {}", user, channel),
+            VoiceEventKind::ServerMuted => {
+                format!(
+                    "{} was server muted{} in {}",
+                    user,
+                    self.by_moderator(),
+                    channel
+                )
+            }
+            VoiceEventKind::ServerUnmuted => {
+                format!(
+                    "{} was server unmuted{} in {}",
+                    user,
+                    self.by_moderator(),
+                    channel
+                )
+            }
+            VoiceEventKind::ServerDeafened => {
+                format!(
+                    "{} was server deafened{} in {}",
+                    user,
+                    self.by_moderator(),
+                    channel
+                )
+            }
+            VoiceEventKind::ServerUndeafened => {
+                format!(
+                    "{} was server undeafened{} in {}",
+                    user,
+                    self.by_moderator(),
+                    channel
+                )
+            }
+            VoiceEventKind::Suppressed => {
+                format!("{} was moved to the audience in {}", user, channel)
+            }
+            VoiceEventKind::Unsuppressed => format!("{} became a speaker in {}", user, channel),
+        }
+    }
+}
+
+impl VoiceEvent {
+    fn by_moderator(&self) -> String {
+        match self.moderator {
+            Some(ref moderator) => format!(" by {}", moderator),
+            None => String::default(),
+        }
+    }
+}
+
+impl fmt::Display for VoiceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.description(), self.guild_name)
+    }
+}
+
+/// Time a member spent in one voice channel, built from the voice events by
+/// [`SessionTracker`](crate::sessions::SessionTracker).
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoiceSession {
+    pub guild_id: GuildId,
+    pub guild_name: String,
+    pub user_id: UserId,
+    pub user_name: String,
+    pub channel_id: ChannelId,
+    pub channel_name: String,
+
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+
+    /// Time spent self or server muted.
+    pub muted: TimeDelta,
+    /// Time spent self or server deafened.
+    pub deafened: TimeDelta,
+    pub streaming: TimeDelta,
+}
+
+impl VoiceSession {
+    pub fn duration(&self) -> TimeDelta {
+        self.end - self.start
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RosterMember {
+    pub user_id: UserId,
+    pub user_name: String,
+    pub flags: VoiceFlags,
+}
+
+#[derive(Debug, Clone)]
+pub struct RosterChannel {
+    pub channel_id: ChannelId,
+    pub channel_name: String,
+    pub members: Vec<RosterMember>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RosterGuild {
+    pub guild_id: GuildId,
+    pub guild_name: String,
+    pub channels: Vec<RosterChannel>,
+}
+
+/// Who is in which voice channel right now, kept up to date from the
+/// voice events. Guilds, channels and members are sorted by name.
+#[derive(Debug, Clone, Default)]
+pub struct Roster {
+    pub guilds: Vec<RosterGuild>,
+}
+
+impl Roster {
+    pub fn apply(&mut self, event: &VoiceEvent) {
+        let guild = match self
+            .guilds
+            .iter_mut()
+            .position(|guild| guild.guild_id == event.guild_id)
+        {
+            Some(idx) => &mut self.guilds[idx],
+            None => {
+                self.guilds.push(RosterGuild {
+                    guild_id: event.guild_id,
+                    guild_name: event.guild_name.clone(),
+                    channels: vec![],
+                });
+                self.guilds.sort_by(|a, b| {
+                    a.guild_name
+                        .to_lowercase()
+                        .cmp(&b.guild_name.to_lowercase())
+                });
+                self.guilds
+                    .iter_mut()
+                    .find(|guild| guild.guild_id == event.guild_id)
+                    .unwrap()
+            }
+        };
+
+        for channel in guild.channels.iter_mut() {
+            channel
+                .members
+                .retain(|member| member.user_id != event.user_id);
+        }
+        guild.channels.retain(|channel| !channel.members.is_empty());
+
+        if event.kind == VoiceEventKind::Left {
+            return;
+        }
+
+        let channel = match guild
+            .channels
+            .iter()
+            .position(|channel| channel.channel_id == event.channel_id)
+        {
+            Some(idx) => &mut guild.channels[idx],
+            None => {
+                guild.channels.push(RosterChannel {
+                    channel_id: event.channel_id,
+                    channel_name: event.channel_name.clone(),
+                    members: vec![],
+                });
+                guild.channels.sort_by(|a, b| {
+                    a.channel_name
+                        .to_lowercase()
+                        .cmp(&b.channel_name.to_lowercase())
+                });
+                guild
+                    .channels
+                    .iter_mut()
+                    .find(|channel| channel.channel_id == event.channel_id)
+                    .unwrap()
+            }
+        };
+
+        channel.members.push(RosterMember {
+            user_id: event.user_id,
+            user_name: event.user_name.clone(),
+            flags: event.after,
+        });
+        channel
+            .members
+            .sort_by(|a, b| a.user_name.to_lowercase().cmp(&b.user_name.to_lowercase()));
+    }
+
+    /// Number of members in voice across all guilds.
+    pub fn member_count(&self) -> usize {
+        self.guilds
+            .iter()
+            .flat_map(|guild| guild.channels.iter())
+            .map(|channel| channel.members.len())
+            .sum()
+    }
+}
@@ -0,0 +1,375 @@
+use std::{fs, io, path::PathBuf};
+
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{ChannelId, GuildId, RoleId, UserId};
+
+use crate::{enums::VoiceEventKind, models::VoiceEvent, utils::config_dir};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    /// Show a desktop notification.
+    Toast,
+    /// Play the notification sound.
+    Sound,
+    /// Only add the event to the event list.
+    LogOnly,
+    /// Drop the event entirely.
+    Suppress,
+}
+
+impl RuleAction {
+    pub const ALL: [RuleAction; 4] = [
+        RuleAction::Toast,
+        RuleAction::Sound,
+        RuleAction::LogOnly,
+        RuleAction::Suppress,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RuleAction::Toast => "Desktop toast",
+            RuleAction::Sound => "Sound",
+            RuleAction::LogOnly => "Log only",
+            RuleAction::Suppress => "Suppress",
+        }
+    }
+}
+
+/// Time of day a rule applies to. A window whose `start` is after its `end`
+/// wraps around midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TimeWindow {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+/// A notification rule. Empty matchers match everything.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rule {
+    pub name: String,
+    pub enabled: bool,
+
+    pub kinds: Vec<VoiceEventKind>,
+    pub guilds: Vec<GuildId>,
+    pub channels: Vec<ChannelId>,
+    pub users: Vec<UserId>,
+    pub roles: Vec<RoleId>,
+    pub time: Option<TimeWindow>,
+
+    pub actions: Vec<RuleAction>,
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Rule {
+            name: "New rule".to_owned(),
+            enabled: true,
+            kinds: vec![],
+            guilds: vec![],
+            channels: vec![],
+            users: vec![],
+            roles: vec![],
+            time: None,
+            actions: vec![RuleAction::Toast, RuleAction::Sound],
+        }
+    }
+}
+
+impl Rule {
+    pub fn matches(&self, event: &VoiceEvent, time: NaiveTime) -> bool {
+        let channel_matches = self.channels.is_empty()
+            || self.channels.contains(&event.channel_id)
+            || event
+                .from_channel_id
+                .is_some_and(|channel_id| self.channels.contains(&channel_id));
+
+        self.enabled
+            && (self.kinds.is_empty() || self.kinds.contains(&event.kind))
+            && (self.guilds.is_empty() || self.guilds.contains(&event.guild_id))
+            && channel_matches
+            && (self.users.is_empty() || self.users.contains(&event.user_id))
+            && (self.roles.is_empty() || self.roles.iter().any(|r| event.roles.contains(r)))
+            && self.time.map_or(true, |window| window.contains(time))
+    }
+}
+
+/// What to do with an event once the rules have been applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RuleOutcome {
+    pub log: bool,
+    pub toast: bool,
+    pub sound: bool,
+}
+
+impl RuleOutcome {
+    /// Outcome of events no rule matches.
+    pub const DEFAULT: RuleOutcome = RuleOutcome {
+        log: true,
+        toast: true,
+        sound: true,
+    };
+
+    fn from_actions(actions: &[RuleAction]) -> Self {
+        if actions.contains(&RuleAction::Suppress) {
+            return RuleOutcome {
+                log: false,
+                toast: false,
+                sound: false,
+            };
+        }
+
+        let log_only = actions.contains(&RuleAction::LogOnly);
+        RuleOutcome {
+            log: true,
+            toast: !log_only && actions.contains(&RuleAction::Toast),
+            sound: !log_only && actions.contains(&RuleAction::Sound),
+        }
+    }
+}
+
+/// Applies the first rule matching `event` at `time`, in order.
+pub fn evaluate(rules: &[Rule], event: &VoiceEvent, time: NaiveTime) -> RuleOutcome {
+    match rules.iter().find(|rule| rule.matches(event, time)) {
+        Some(rule) => RuleOutcome::from_actions(&rule.actions),
+        None => RuleOutcome::DEFAULT,
+    }
+}
+
+/// Rules stored in `$XDG_CONFIG_HOME/discord_watcher/rules.toml`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RuleSet {
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    /// Why the rules file could not be loaded, it is not overwritten then.
+    #[serde(skip)]
+    pub load_error: Option<String>,
+}
+
+impl RuleSet {
+    pub fn path() -> PathBuf {
+        config_dir().join("rules.toml")
+    }
+
+    /// Loads the rules file, falling back to no rules when it is missing or
+    /// invalid. An invalid file is left as is by [`RuleSet::save`].
+    pub fn load() -> Self {
+        let load_error = match fs::read_to_string(Self::path()) {
+            Ok(content) => match toml::from_str(&content) {
+                Ok(rules) => return rules,
+                Err(e) => format!("Error when parsing rules: {}", e),
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return RuleSet::default(),
+            Err(e) => format!("Error when reading rules: {}", e),
+        };
+        eprintln!("{}", load_error);
+        RuleSet {
+            load_error: Some(load_error),
+            ..RuleSet::default()
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(ref load_error) = self.load_error {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} was not loaded, fix or remove it first ({})",
+                    Self::path().display(),
+                    load_error
+                ),
+            ));
+        }
+        let content =
+            toml::to_string_pretty(self).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        fs::create_dir_all(config_dir())?;
+        fs::write(Self::path(), content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{voice_event, ALICE, BOB, GAMING, GENERAL};
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn rule(actions: &[RuleAction]) -> Rule {
+        Rule {
+            actions: actions.to_vec(),
+            ..Rule::default()
+        }
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let event = voice_event(VoiceEventKind::Joined, ALICE, GENERAL);
+        let rules = [
+            Rule {
+                enabled: false,
+                ..rule(&[RuleAction::Suppress])
+            },
+            Rule {
+                kinds: vec![VoiceEventKind::Left],
+                ..rule(&[RuleAction::Suppress])
+            },
+            rule(&[RuleAction::Sound]),
+            rule(&[RuleAction::Toast]),
+        ];
+
+        let outcome = evaluate(&rules, &event, time(12, 0));
+        assert_eq!(
+            outcome,
+            RuleOutcome {
+                log: true,
+                toast: false,
+                sound: true,
+            }
+        );
+        assert_eq!(evaluate(&[], &event, time(12, 0)), RuleOutcome::DEFAULT);
+    }
+
+    #[test]
+    fn time_windows_wrap_past_midnight() {
+        let day = TimeWindow {
+            start: time(9, 0),
+            end: time(17, 0),
+        };
+        let night = TimeWindow {
+            start: time(22, 0),
+            end: time(6, 0),
+        };
+
+        for (at, in_day, in_night) in [
+            (time(9, 0), true, false),
+            (time(16, 59), true, false),
+            (time(17, 0), false, false),
+            (time(22, 0), false, true),
+            (time(0, 0), false, true),
+            (time(5, 59), false, true),
+            (time(6, 0), false, false),
+        ] {
+            assert_eq!(day.contains(at), in_day, "{} in {:?}", at, day);
+            assert_eq!(night.contains(at), in_night, "{} in {:?}", at, night);
+        }
+    }
+
+    #[test]
+    fn matches_users_roles_and_channels() {
+        let role = RoleId(500);
+        let mut event = voice_event(VoiceEventKind::Moved, ALICE, GAMING);
+        event.roles = vec![role];
+        event.from_channel_id = Some(GENERAL.into());
+        let noon = time(12, 0);
+
+        for (matcher, expected) in [
+            (
+                Rule {
+                    users: vec![ALICE.into()],
+                    ..Rule::default()
+                },
+                true,
+            ),
+            (
+                Rule {
+                    users: vec![BOB.into()],
+                    ..Rule::default()
+                },
+                false,
+            ),
+            (
+                Rule {
+                    roles: vec![RoleId(501), role],
+                    ..Rule::default()
+                },
+                true,
+            ),
+            (
+                Rule {
+                    roles: vec![RoleId(501)],
+                    ..Rule::default()
+                },
+                false,
+            ),
+            (
+                Rule {
+                    channels: vec![GAMING.into()],
+                    ..Rule::default()
+                },
+                true,
+            ),
+            // A move matches the channel it comes from too
+            (
+                Rule {
+                    channels: vec![GENERAL.into()],
+                    ..Rule::default()
+                },
+                true,
+            ),
+            (
+                Rule {
+                    channels: vec![GENERAL.into()],
+                    users: vec![BOB.into()],
+                    ..Rule::default()
+                },
+                false,
+            ),
+        ] {
+            assert_eq!(matcher.matches(&event, noon), expected, "{:?}", matcher);
+        }
+
+        let joined = voice_event(VoiceEventKind::Joined, ALICE, GAMING);
+        let general = Rule {
+            channels: vec![GENERAL.into()],
+            ..Rule::default()
+        };
+        assert!(!general.matches(&joined, noon));
+    }
+
+    #[test]
+    fn combines_actions() {
+        let outcome = |log, toast, sound| RuleOutcome { log, toast, sound };
+
+        for (actions, expected) in [
+            (vec![], outcome(true, false, false)),
+            (vec![RuleAction::Toast], outcome(true, true, false)),
+            (
+                vec![RuleAction::Toast, RuleAction::Sound],
+                outcome(true, true, true),
+            ),
+            (
+                vec![RuleAction::LogOnly, RuleAction::Toast, RuleAction::Sound],
+                outcome(true, false, false),
+            ),
+            (
+                vec![RuleAction::Suppress, RuleAction::Toast],
+                outcome(false, false, false),
+            ),
+            (
+                vec![RuleAction::LogOnly, RuleAction::Suppress],
+                outcome(false, false, false),
+            ),
+        ] {
+            assert_eq!(
+                RuleOutcome::from_actions(&actions),
+                expected,
+                "{:?}",
+                actions
+            );
+        }
+    }
+}
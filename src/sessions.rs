@@ -0,0 +1,469 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Datelike, NaiveDate, TimeDelta, TimeZone, Timelike, Utc};
+use serenity::model::id::{ChannelId, GuildId, UserId};
+
+use crate::{
+    enums::VoiceEventKind,
+    models::{VoiceEvent, VoiceFlags, VoiceSession},
+};
+
+/// Days covered by the longest sessions, busiest channels and heatmap.
+pub const RECENT_DAYS: i64 = 30;
+
+/// Rows shown in the longest sessions and busiest channels lists.
+const TOP_COUNT: usize = 10;
+
+#[derive(Clone)]
+struct OpenSession {
+    session: VoiceSession,
+    flags: VoiceFlags,
+    /// When `flags` were last changed.
+    since: DateTime<Utc>,
+}
+
+impl OpenSession {
+    fn start(event: &VoiceEvent) -> Self {
+        OpenSession {
+            session: VoiceSession {
+                guild_id: event.guild_id,
+                guild_name: event.guild_name.clone(),
+                user_id: event.user_id,
+                user_name: event.user_name.clone(),
+                channel_id: event.channel_id,
+                channel_name: event.channel_name.clone(),
+                start: event.timestamp,
+                end: event.timestamp,
+                muted: TimeDelta::zero(),
+                deafened: TimeDelta::zero(),
+                streaming: TimeDelta::zero(),
+            },
+            flags: event.after,
+            since: event.timestamp,
+        }
+    }
+
+    /// Counts the time spent with the previous flags and switches to `flags`.
+    fn set_flags(&mut self, time: DateTime<Utc>, flags: VoiceFlags) {
+        let elapsed = (time - self.since).max(TimeDelta::zero());
+        if self.flags.self_mute || self.flags.mute {
+            self.session.muted += elapsed;
+        }
+        if self.flags.self_deaf || self.flags.deaf {
+            self.session.deafened += elapsed;
+        }
+        if self.flags.self_stream {
+            self.session.streaming += elapsed;
+        }
+        self.flags = flags;
+        self.since = self.since.max(time);
+    }
+
+    fn close(mut self, time: DateTime<Utc>) -> VoiceSession {
+        self.set_flags(time, self.flags);
+        self.session.end = self.since;
+        self.session
+    }
+}
+
+/// Turns the voice events of the handler into [`VoiceSession`]s.
+///
+/// A session starts when a member is first seen in a channel and ends when
+/// they leave or move to another one. Sessions still open when the watcher
+/// stops end then, see [`SessionTracker::close_all`].
+#[derive(Default)]
+pub struct SessionTracker {
+    open: HashMap<(GuildId, UserId), OpenSession>,
+}
+
+impl SessionTracker {
+    /// Applies `event`, returns the session it ended if any.
+    pub fn apply(&mut self, event: &VoiceEvent) -> Option<VoiceSession> {
+        let key = (event.guild_id, event.user_id);
+        if event.kind == VoiceEventKind::Left {
+            return self
+                .open
+                .remove(&key)
+                .map(|open| open.close(event.timestamp));
+        }
+
+        match self.open.get_mut(&key) {
+            Some(open) if open.session.channel_id == event.channel_id => {
+                open.set_flags(event.timestamp, event.after);
+                None
+            }
+            // A move, or a leave missed while disconnected
+            _ => self
+                .open
+                .insert(key, OpenSession::start(event))
+                .map(|open| open.close(event.timestamp)),
+        }
+    }
+
+    /// The sessions still open, as if they ended at `now`.
+    pub fn open_sessions(&self, now: DateTime<Utc>) -> Vec<VoiceSession> {
+        self.open
+            .values()
+            .map(|open| open.clone().close(now))
+            .collect()
+    }
+
+    /// Ends every open session at `now`, for them to be saved before the
+    /// tracker is dropped.
+    pub fn close_all(&mut self, now: DateTime<Utc>) -> Vec<VoiceSession> {
+        self.open.drain().map(|(_, open)| open.close(now)).collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserTotals {
+    pub user_id: UserId,
+    pub user_name: String,
+    pub today: TimeDelta,
+    pub week: TimeDelta,
+    pub month: TimeDelta,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelTotals {
+    pub channel_id: ChannelId,
+    pub channel_name: String,
+    pub guild_name: String,
+    pub sessions: usize,
+    pub time: TimeDelta,
+}
+
+/// Time in voice computed from the stored sessions, see
+/// [`Statistics::compute`].
+#[derive(Debug, Clone, Default)]
+pub struct Statistics {
+    /// Time per user today, this week and this month, most active first.
+    pub users: Vec<UserTotals>,
+    /// Longest sessions of the last [`RECENT_DAYS`].
+    pub longest: Vec<VoiceSession>,
+    /// Channels with the most time in voice over the last [`RECENT_DAYS`].
+    pub channels: Vec<ChannelTotals>,
+    /// Time in voice over the last [`RECENT_DAYS`] by weekday, Monday
+    /// first, and hour of the day.
+    pub heatmap: [[TimeDelta; 24]; 7],
+}
+
+impl Statistics {
+    /// Sessions ending after this time are needed to compute the statistics
+    /// at `now`.
+    pub fn since<Tz: TimeZone>(now: &DateTime<Tz>) -> DateTime<Utc> {
+        let (_, week, month) = periods(now);
+        recent_start(now).min(week).min(month)
+    }
+
+    /// Computes the statistics at `now`, days and hours are counted in the
+    /// timezone of `now`. `sessions` should include the open ones, see
+    /// [`SessionTracker::open_sessions`], time after `now` is left out.
+    pub fn compute<Tz: TimeZone>(sessions: &[VoiceSession], now: &DateTime<Tz>) -> Self {
+        let (today, week, month) = periods(now);
+        let recent = recent_start(now);
+        let end = now.with_timezone(&Utc);
+
+        let mut users: Vec<UserTotals> = vec![];
+        let mut channels: Vec<ChannelTotals> = vec![];
+        let mut heatmap = [[TimeDelta::zero(); 24]; 7];
+        for session in sessions {
+            let user = match users
+                .iter()
+                .position(|user| user.user_id == session.user_id)
+            {
+                Some(idx) => &mut users[idx],
+                None => {
+                    users.push(UserTotals {
+                        user_id: session.user_id,
+                        user_name: session.user_name.clone(),
+                        today: TimeDelta::zero(),
+                        week: TimeDelta::zero(),
+                        month: TimeDelta::zero(),
+                    });
+                    users.last_mut().unwrap()
+                }
+            };
+            user.today += overlap(session, today, end);
+            user.week += overlap(session, week, end);
+            user.month += overlap(session, month, end);
+
+            let time = overlap(session, recent, end);
+            if time <= TimeDelta::zero() {
+                continue;
+            }
+            match channels
+                .iter_mut()
+                .find(|channel| channel.channel_id == session.channel_id)
+            {
+                Some(channel) => {
+                    channel.sessions += 1;
+                    channel.time += time;
+                }
+                None => channels.push(ChannelTotals {
+                    channel_id: session.channel_id,
+                    channel_name: session.channel_name.clone(),
+                    guild_name: session.guild_name.clone(),
+                    sessions: 1,
+                    time,
+                }),
+            }
+
+            // Split the session at every hour of the timezone of `now`
+            let mut cursor = session.start.max(recent);
+            let session_end = session.end.min(end);
+            while cursor < session_end {
+                let local = cursor.with_timezone(&now.timezone());
+                let into_hour = TimeDelta::seconds(i64::from(local.minute() * 60 + local.second()))
+                    + TimeDelta::nanoseconds(i64::from(local.nanosecond()));
+                let next = (cursor - into_hour + TimeDelta::hours(1)).min(session_end);
+                heatmap[local.weekday().num_days_from_monday() as usize][local.hour() as usize] +=
+                    next - cursor;
+                cursor = next;
+            }
+        }
+
+        users.retain(|user| user.month > TimeDelta::zero() || user.week > TimeDelta::zero());
+        users.sort_by(|a, b| b.month.cmp(&a.month).then(b.week.cmp(&a.week)));
+        channels.sort_by(|a, b| b.time.cmp(&a.time));
+        channels.truncate(TOP_COUNT);
+
+        let mut longest = sessions
+            .iter()
+            .filter(|session| session.end > recent)
+            .cloned()
+            .collect::<Vec<_>>();
+        longest.sort_by_key(|session| -session.duration());
+        longest.truncate(TOP_COUNT);
+
+        Statistics {
+            users,
+            longest,
+            channels,
+            heatmap,
+        }
+    }
+}
+
+/// Starts of today, this week (Monday) and this month in the timezone of
+/// `now`.
+fn periods<Tz: TimeZone>(now: &DateTime<Tz>) -> (DateTime<Utc>, DateTime<Utc>, DateTime<Utc>) {
+    let date = now.date_naive();
+    let week = date - TimeDelta::days(i64::from(date.weekday().num_days_from_monday()));
+    let month = date.with_day(1).unwrap_or(date);
+    (
+        midnight(now, date),
+        midnight(now, week),
+        midnight(now, month),
+    )
+}
+
+fn recent_start<Tz: TimeZone>(now: &DateTime<Tz>) -> DateTime<Utc> {
+    now.with_timezone(&Utc) - TimeDelta::days(RECENT_DAYS)
+}
+
+/// Start of `date` in the timezone of `now`.
+pub fn midnight<Tz: TimeZone>(now: &DateTime<Tz>, date: NaiveDate) -> DateTime<Utc> {
+    date.and_hms_opt(0, 0, 0)
+        .and_then(|time| now.timezone().from_local_datetime(&time).earliest())
+        // Days starting in a DST gap
+        .map(|time| time.with_timezone(&Utc))
+        .unwrap_or_else(|| now.with_timezone(&Utc))
+}
+
+/// Part of `session` between `from` and `to`.
+fn overlap(session: &VoiceSession, from: DateTime<Utc>, to: DateTime<Utc>) -> TimeDelta {
+    (session.end.min(to) - session.start.max(from)).max(TimeDelta::zero())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{voice_event, ALICE, BOB, GAMING, GENERAL};
+
+    /// 2024-05-15 is a Wednesday.
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, day, hour, minute, 0).unwrap()
+    }
+
+    fn event(
+        kind: VoiceEventKind,
+        user: u64,
+        channel: u64,
+        time: DateTime<Utc>,
+        after: VoiceFlags,
+    ) -> VoiceEvent {
+        VoiceEvent {
+            timestamp: time,
+            after,
+            ..voice_event(kind, user, channel)
+        }
+    }
+
+    fn session(user: u64, channel: u64, start: DateTime<Utc>, end: DateTime<Utc>) -> VoiceSession {
+        let mut open = OpenSession::start(&event(
+            VoiceEventKind::Joined,
+            user,
+            channel,
+            start,
+            VoiceFlags::default(),
+        ));
+        open.session.end = end;
+        open.session
+    }
+
+    #[test]
+    fn tracks_flags_until_the_member_leaves() {
+        let mut tracker = SessionTracker::default();
+        let muted = VoiceFlags {
+            self_mute: true,
+            ..Default::default()
+        };
+        let streaming = VoiceFlags {
+            self_stream: true,
+            ..Default::default()
+        };
+
+        for (kind, minute, flags) in [
+            (VoiceEventKind::Joined, 0, VoiceFlags::default()),
+            (VoiceEventKind::Muted, 10, muted),
+            (VoiceEventKind::Unmuted, 25, VoiceFlags::default()),
+            (VoiceEventKind::StartedStreaming, 30, streaming),
+        ] {
+            let ended = tracker.apply(&event(kind, ALICE, GENERAL, at(15, 20, minute), flags));
+            assert_eq!(ended, None);
+        }
+        let ended = tracker
+            .apply(&event(
+                VoiceEventKind::Left,
+                ALICE,
+                GENERAL,
+                at(15, 21, 0),
+                VoiceFlags::default(),
+            ))
+            .unwrap();
+
+        assert_eq!(ended.start, at(15, 20, 0));
+        assert_eq!(ended.end, at(15, 21, 0));
+        assert_eq!(ended.muted, TimeDelta::minutes(15));
+        assert_eq!(ended.deafened, TimeDelta::zero());
+        assert_eq!(ended.streaming, TimeDelta::minutes(30));
+        assert_eq!(
+            tracker.apply(&event(
+                VoiceEventKind::Left,
+                ALICE,
+                GENERAL,
+                at(15, 21, 5),
+                VoiceFlags::default()
+            )),
+            None
+        );
+    }
+
+    #[test]
+    fn a_move_starts_a_new_session() {
+        let mut tracker = SessionTracker::default();
+        let flags = VoiceFlags::default();
+        tracker.apply(&event(
+            VoiceEventKind::AlreadyIn,
+            ALICE,
+            GENERAL,
+            at(15, 20, 0),
+            flags,
+        ));
+
+        let ended = tracker
+            .apply(&event(
+                VoiceEventKind::Moved,
+                ALICE,
+                GAMING,
+                at(15, 20, 40),
+                flags,
+            ))
+            .unwrap();
+        assert_eq!(ended.channel_name, "General");
+        assert_eq!(ended.duration(), TimeDelta::minutes(40));
+
+        let ended = tracker
+            .apply(&event(
+                VoiceEventKind::Left,
+                ALICE,
+                GAMING,
+                at(15, 21, 0),
+                flags,
+            ))
+            .unwrap();
+        assert_eq!(ended.channel_name, "Gaming");
+        assert_eq!(ended.start, at(15, 20, 40));
+    }
+
+    #[test]
+    fn open_sessions_end_now() {
+        let mut tracker = SessionTracker::default();
+        let muted = VoiceFlags {
+            self_mute: true,
+            ..Default::default()
+        };
+        tracker.apply(&event(
+            VoiceEventKind::Joined,
+            ALICE,
+            GENERAL,
+            at(15, 20, 0),
+            VoiceFlags::default(),
+        ));
+        tracker.apply(&event(
+            VoiceEventKind::Muted,
+            ALICE,
+            GENERAL,
+            at(15, 20, 10),
+            muted,
+        ));
+
+        let open = tracker.open_sessions(at(15, 20, 30));
+        assert_eq!(open.len(), 1);
+        assert_eq!(open[0].end, at(15, 20, 30));
+        assert_eq!(open[0].muted, TimeDelta::minutes(20));
+
+        // Still open until closed
+        let closed = tracker.close_all(at(15, 21, 0));
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].end, at(15, 21, 0));
+        assert_eq!(closed[0].muted, TimeDelta::minutes(50));
+        assert!(tracker.close_all(at(15, 21, 0)).is_empty());
+    }
+
+    #[test]
+    fn splits_totals_by_period() {
+        // Wednesday afternoon
+        let now = at(15, 18, 0);
+        let sessions = [
+            // Today, crossing 14:00
+            session(ALICE, GENERAL, at(15, 13, 30), at(15, 14, 30)),
+            // Monday, this week
+            session(ALICE, GAMING, at(13, 10, 0), at(13, 12, 0)),
+            // Earlier this month
+            session(ALICE, GENERAL, at(2, 10, 0), at(2, 13, 0)),
+            // Last month
+            session(BOB, GENERAL, at(1, 0, 0) - TimeDelta::hours(2), at(1, 0, 0)),
+        ];
+        assert!(Statistics::since(&now) <= at(1, 0, 0) - TimeDelta::hours(2));
+
+        let statistics = Statistics::compute(&sessions, &now);
+
+        assert_eq!(statistics.users.len(), 1);
+        let alice = &statistics.users[0];
+        assert_eq!(alice.today, TimeDelta::hours(1));
+        assert_eq!(alice.week, TimeDelta::hours(3));
+        assert_eq!(alice.month, TimeDelta::hours(6));
+
+        assert_eq!(statistics.longest[0].start, at(2, 10, 0));
+        assert_eq!(statistics.channels[0].channel_name, "General");
+        assert_eq!(statistics.channels[0].sessions, 3);
+        assert_eq!(statistics.channels[0].time, TimeDelta::hours(6));
+
+        let wednesday = &statistics.heatmap[2];
+        assert_eq!(wednesday[13], TimeDelta::minutes(30));
+        assert_eq!(wednesday[14], TimeDelta::minutes(30));
+        assert_eq!(statistics.heatmap[0][11], TimeDelta::hours(1));
+    }
+}
@@ -0,0 +1,255 @@
+use std::{fs, path::PathBuf};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use rusqlite::{params, Connection, Row};
+
+use crate::{
+    enums::VoiceEventKind,
+    models::{VoiceEvent, VoiceFlags, VoiceSession},
+    utils::data_dir,
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS voice_events (
+    id                INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp         INTEGER NOT NULL,
+    kind              TEXT    NOT NULL,
+    guild_id          INTEGER NOT NULL,
+    guild_name        TEXT    NOT NULL,
+    user_id           INTEGER NOT NULL,
+    user_name         TEXT    NOT NULL,
+    channel_id        INTEGER NOT NULL,
+    channel_name      TEXT    NOT NULL,
+    from_channel_id   INTEGER,
+    from_channel_name TEXT,
+    before_flags      INTEGER NOT NULL,
+    after_flags       INTEGER NOT NULL,
+    moderator         TEXT
+);
+CREATE INDEX IF NOT EXISTS voice_events_timestamp ON voice_events (timestamp);
+CREATE INDEX IF NOT EXISTS voice_events_guild ON voice_events (guild_id, timestamp);
+CREATE INDEX IF NOT EXISTS voice_events_channel ON voice_events (channel_id, timestamp);
+CREATE INDEX IF NOT EXISTS voice_events_user ON voice_events (user_id, timestamp);
+CREATE TABLE IF NOT EXISTS voice_sessions (
+    id           INTEGER PRIMARY KEY AUTOINCREMENT,
+    start_time   INTEGER NOT NULL,
+    end_time     INTEGER NOT NULL,
+    guild_id     INTEGER NOT NULL,
+    guild_name   TEXT    NOT NULL,
+    user_id      INTEGER NOT NULL,
+    user_name    TEXT    NOT NULL,
+    channel_id   INTEGER NOT NULL,
+    channel_name TEXT    NOT NULL,
+    muted        INTEGER NOT NULL,
+    deafened     INTEGER NOT NULL,
+    streaming    INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS voice_sessions_end ON voice_sessions (end_time);
+";
+
+/// Changes to the schema of older databases, the `user_version` of the
+/// database is the number of migrations already applied.
+const MIGRATIONS: [&str; 1] =
+    ["ALTER TABLE voice_events ADD COLUMN resynced INTEGER NOT NULL DEFAULT 0;"];
+
+const COLUMNS: &str = "id, timestamp, kind, guild_id, guild_name, user_id, user_name, \
+    channel_id, channel_name, from_channel_id, from_channel_name, before_flags, after_flags, \
+    moderator, resynced";
+
+/// A voice event read back from the database, with its row ID.
+#[derive(Debug, Clone)]
+pub struct StoredEvent {
+    pub id: i64,
+    pub event: VoiceEvent,
+}
+
+/// Event history kept in `$XDG_DATA_HOME/discord_watcher/events.db`.
+pub struct EventStore {
+    conn: Connection,
+}
+
+impl EventStore {
+    pub fn path() -> PathBuf {
+        data_dir().join("events.db")
+    }
+
+    pub fn open() -> rusqlite::Result<Self> {
+        if let Err(e) = fs::create_dir_all(data_dir()) {
+            eprintln!("Error when creating data directory: {}", e);
+        }
+        Self::open_at(Self::path())
+    }
+
+    pub fn open_at(path: PathBuf) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        // WAL lets the UI read while the Discord handler writes.
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.execute_batch(SCHEMA)?;
+        migrate(&conn)?;
+        Ok(EventStore { conn })
+    }
+
+    pub fn insert(&self, event: &VoiceEvent) -> rusqlite::Result<i64> {
+        self.conn.execute(
+            "INSERT INTO voice_events (timestamp, kind, guild_id, guild_name, user_id, \
+             user_name, channel_id, channel_name, from_channel_id, from_channel_name, \
+             before_flags, after_flags, moderator, resynced) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            params![
+                event.timestamp.timestamp_millis(),
+                event.kind.key(),
+                event.guild_id.0 as i64,
+                event.guild_name,
+                event.user_id.0 as i64,
+                event.user_name,
+                event.channel_id.0 as i64,
+                event.channel_name,
+                event.from_channel_id.map(|id| id.0 as i64),
+                event.from_channel_name,
+                event.before.bits(),
+                event.after.bits(),
+                event.moderator,
+                event.resynced,
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn insert_session(&self, session: &VoiceSession) -> rusqlite::Result<i64> {
+        self.conn.execute(
+            "INSERT INTO voice_sessions (start_time, end_time, guild_id, guild_name, user_id, user_name, \
+             channel_id, channel_name, muted, deafened, streaming) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                session.start.timestamp_millis(),
+                session.end.timestamp_millis(),
+                session.guild_id.0 as i64,
+                session.guild_name,
+                session.user_id.0 as i64,
+                session.user_name,
+                session.channel_id.0 as i64,
+                session.channel_name,
+                session.muted.num_milliseconds(),
+                session.deafened.num_milliseconds(),
+                session.streaming.num_milliseconds(),
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Sessions that ended after `time`, oldest first.
+    pub fn sessions_since(&self, time: DateTime<Utc>) -> rusqlite::Result<Vec<VoiceSession>> {
+        let mut statement = self.conn.prepare_cached(
+            "SELECT start_time, end_time, guild_id, guild_name, user_id, user_name, channel_id, \
+             channel_name, muted, deafened, streaming FROM voice_sessions \
+             WHERE end_time > ?1 ORDER BY start_time",
+        )?;
+        let rows = statement.query_map(params![time.timestamp_millis()], read_session)?;
+        rows.collect()
+    }
+
+    /// Newest `limit` events, newest first.
+    pub fn latest(&self, limit: usize) -> rusqlite::Result<Vec<StoredEvent>> {
+        self.before(i64::MAX, limit)
+    }
+
+    /// Up to `limit` events older than the event `id`, newest first.
+    pub fn before(&self, id: i64, limit: usize) -> rusqlite::Result<Vec<StoredEvent>> {
+        let mut statement = self.conn.prepare_cached(&format!(
+            "SELECT {} FROM voice_events WHERE id < ?1 ORDER BY id DESC LIMIT ?2",
+            COLUMNS
+        ))?;
+        let rows = statement.query_map(params![id, limit as i64], read_row)?;
+        rows.collect()
+    }
+
+    /// Events from `from` up to `to` excluded, oldest first. `None` leaves
+    /// that side of the range open.
+    pub fn between(
+        &self,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> rusqlite::Result<Vec<StoredEvent>> {
+        let mut statement = self.conn.prepare_cached(&format!(
+            "SELECT {} FROM voice_events WHERE timestamp >= ?1 AND timestamp < ?2 \
+             ORDER BY timestamp, id",
+            COLUMNS
+        ))?;
+        let rows = statement.query_map(
+            params![
+                from.map_or(i64::MIN, |time| time.timestamp_millis()),
+                to.map_or(i64::MAX, |time| time.timestamp_millis()),
+            ],
+            read_row,
+        )?;
+        rows.collect()
+    }
+}
+
+fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for (idx, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        conn.execute_batch(migration)?;
+        conn.pragma_update(None, "user_version", idx + 1)?;
+    }
+    Ok(())
+}
+
+fn read_session(row: &Row<'_>) -> rusqlite::Result<VoiceSession> {
+    let time = |column: &str| -> rusqlite::Result<DateTime<Utc>> {
+        Ok(DateTime::<Utc>::from_timestamp_millis(row.get(column)?).unwrap_or_default())
+    };
+    let duration = |column: &str| -> rusqlite::Result<TimeDelta> {
+        Ok(TimeDelta::milliseconds(row.get(column)?))
+    };
+
+    Ok(VoiceSession {
+        guild_id: (row.get::<_, i64>("guild_id")? as u64).into(),
+        guild_name: row.get("guild_name")?,
+        user_id: (row.get::<_, i64>("user_id")? as u64).into(),
+        user_name: row.get("user_name")?,
+        channel_id: (row.get::<_, i64>("channel_id")? as u64).into(),
+        channel_name: row.get("channel_name")?,
+        start: time("start_time")?,
+        end: time("end_time")?,
+        muted: duration("muted")?,
+        deafened: duration("deafened")?,
+        streaming: duration("streaming")?,
+    })
+}
+
+fn read_row(row: &Row<'_>) -> rusqlite::Result<StoredEvent> {
+    let kind: String = row.get("kind")?;
+    let kind = VoiceEventKind::from_key(&kind).ok_or_else(|| {
+        rusqlite::Error::FromSqlConversionFailure(
+            2,
+            rusqlite::types::Type::Text,
+            format!("unknown event kind {}", kind).into(),
+        )
+    })?;
+    let timestamp =
+        DateTime::<Utc>::from_timestamp_millis(row.get("timestamp")?).unwrap_or_default();
+
+    Ok(StoredEvent {
+        id: row.get("id")?,
+        event: VoiceEvent {
+            timestamp,
+            kind,
+            guild_id: (row.get::<_, i64>("guild_id")? as u64).into(),
+            guild_name: row.get("guild_name")?,
+            user_id: (row.get::<_, i64>("user_id")? as u64).into(),
+            user_name: row.get("user_name")?,
+            roles: vec![],
+            channel_id: (row.get::<_, i64>("channel_id")? as u64).into(),
+            channel_name: row.get("channel_name")?,
+            from_channel_id: row
+                .get::<_, Option<i64>>("from_channel_id")?
+                .map(|id| (id as u64).into()),
+            from_channel_name: row.get("from_channel_name")?,
+            before: VoiceFlags::from_bits(row.get("before_flags")?),
+            after: VoiceFlags::from_bits(row.get("after_flags")?),
+            moderator: row.get("moderator")?,
+            resynced: row.get("resynced")?,
+        },
+    })
+}
@@ -0,0 +1,233 @@
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    panic,
+    path::{Path, PathBuf},
+    process, thread,
+};
+
+use dotenv::dotenv;
+use keyring::Entry;
+
+use crate::{config::TokenSource, utils::config_dir};
+
+const KEYRING_SERVICE: &str = "discord_watcher";
+const KEYRING_USER: &str = "bot_token";
+
+/// Where [`store_token`] put the token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenLocation {
+    Keyring,
+    File(PathBuf),
+}
+
+/// Fallback for systems without a Secret Service,
+/// `$XDG_CONFIG_HOME/discord_watcher/token`, readable by the user only.
+pub fn token_file() -> PathBuf {
+    config_dir().join("token")
+}
+
+/// Checks that `token` looks like a bot token, without asking Discord: three
+/// base64url parts separated by dots, the first one being the encoded bot ID.
+pub fn validate_token(token: &str) -> Result<(), String> {
+    let parts: Vec<&str> = token.trim().split('.').collect();
+    let is_base64 = |part: &str| {
+        !part.is_empty()
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    };
+
+    // A snowflake of 17 to 20 digits takes at least 23 characters
+    if parts.len() == 3 && parts.iter().all(|part| is_base64(part)) && parts[0].len() >= 23 {
+        Ok(())
+    } else {
+        Err("This is not a bot token, copy it from the Bot page of the application".to_owned())
+    }
+}
+
+/// Reads the bot token from the configured source, `None` when there is none.
+pub fn load_token(source: &TokenSource) -> Option<String> {
+    match source {
+        TokenSource::Env => {
+            dotenv().ok();
+            env::var("DISCORD_TOKEN").ok()
+        }
+        TokenSource::File(path) => match fs::read_to_string(path) {
+            Ok(token) => Some(token.trim().to_owned()),
+            Err(e) => {
+                eprintln!("Error when reading token file {}: {}", path.display(), e);
+                None
+            }
+        },
+        TokenSource::Keyring => load_stored_token(),
+    }
+}
+
+/// Like [`load_token`], exiting when there is no token. For the frontends
+/// that can't ask for one.
+pub fn require_token(source: &TokenSource) -> String {
+    match load_token(source) {
+        Some(token) => token,
+        None => {
+            eprintln!(
+                "No bot token found, set DISCORD_TOKEN, use --token-file or save one from the app"
+            );
+            process::exit(1);
+        }
+    }
+}
+
+/// Runs `action` on the keyring entry of the token, on a thread of its own:
+/// the Secret Service client blocks on an async runtime, which must not
+/// happen inside the tokio runtime the frontends run in.
+fn with_keyring_entry<T: Send>(
+    action: impl FnOnce(&Entry) -> keyring::Result<T> + Send,
+) -> keyring::Result<T> {
+    thread::scope(|scope| {
+        scope
+            .spawn(|| Entry::new(KEYRING_SERVICE, KEYRING_USER).and_then(|entry| action(&entry)))
+            .join()
+            .unwrap_or_else(|e| panic::resume_unwind(e))
+    })
+}
+
+/// Reads the token saved by [`store_token`].
+pub fn load_stored_token() -> Option<String> {
+    read_stored_token(&token_file())
+}
+
+/// Like [`load_stored_token`] with the fallback file at `path`.
+fn read_stored_token(path: &Path) -> Option<String> {
+    match with_keyring_entry(|entry| entry.get_password()) {
+        Ok(token) => return Some(token),
+        Err(keyring::Error::NoEntry) => (),
+        Err(e) => eprintln!("Error when reading token from the secret service: {}", e),
+    }
+
+    match fs::read_to_string(path) {
+        Ok(token) => Some(token.trim().to_owned()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => {
+            eprintln!("Error when reading token file: {}", e);
+            None
+        }
+    }
+}
+
+/// Saves the token in the Secret Service, or in [`token_file`] when there is
+/// no Secret Service.
+pub fn store_token(token: &str) -> io::Result<TokenLocation> {
+    store_token_at(token, &token_file())
+}
+
+/// Like [`store_token`] with the fallback file at `path`.
+fn store_token_at(token: &str, path: &Path) -> io::Result<TokenLocation> {
+    match with_keyring_entry(|entry| entry.set_password(token)) {
+        Ok(()) => {
+            // Don't leave an older token lying around
+            remove_token_file(path)?;
+            Ok(TokenLocation::Keyring)
+        }
+        Err(e) => {
+            eprintln!("Error when storing token in the secret service: {}", e);
+            write_token_file(token, path)?;
+            Ok(TokenLocation::File(path.to_owned()))
+        }
+    }
+}
+
+/// Removes the token from the Secret Service and the token file.
+pub fn forget_token() -> io::Result<()> {
+    forget_token_at(&token_file())
+}
+
+/// Like [`forget_token`] with the fallback file at `path`.
+fn forget_token_at(path: &Path) -> io::Result<()> {
+    match with_keyring_entry(|entry| entry.delete_credential()) {
+        Ok(()) | Err(keyring::Error::NoEntry) => (),
+        Err(e) => eprintln!("Error when removing token from the secret service: {}", e),
+    }
+    remove_token_file(path)
+}
+
+fn write_token_file(token: &str, path: &Path) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+        options.mode(0o600);
+        // `mode` only applies to new files
+        if let Ok(metadata) = fs::metadata(path) {
+            let mut permissions = metadata.permissions();
+            permissions.set_mode(0o600);
+            fs::set_permissions(path, permissions)?;
+        }
+    }
+
+    let mut file = options.open(path)?;
+    file.write_all(token.as_bytes())
+}
+
+fn remove_token_file(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    #[test]
+    fn validates_token_format() {
+        assert!(
+            validate_token("Mjg4NzYwMjQxMzYzODc3ODg4.C_ikow.j3VupLBuE1QWZng3TMGH0z_UAwg").is_ok()
+        );
+        assert!(
+            validate_token(" Mjg4NzYwMjQxMzYzODc3ODg4.C_ikow.j3VupLBuE1QWZng3TMGH0z_UAwg\n")
+                .is_ok()
+        );
+        assert!(validate_token("").is_err());
+        assert!(validate_token("Mjg4NzYwMjQxMzYzODc3ODg4").is_err());
+        assert!(validate_token("Mjg4NzYwMjQxMzYzODc3ODg4.C_ikow.j3Vu=pLBuE1Q").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stores_token() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = env::temp_dir().join(format!("discord_watcher_token_{}", process::id()));
+        let path = dir.join("token");
+        keyring::set_default_credential_builder(keyring::mock::default_credential_builder());
+
+        write_token_file("file token", &path).unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        assert_eq!(read_stored_token(&path).as_deref(), Some("file token"));
+
+        // The Secret Service takes over from the file
+        assert_eq!(
+            store_token_at("token", &path).unwrap(),
+            TokenLocation::Keyring
+        );
+        assert!(!path.exists());
+
+        write_token_file("file token", &path).unwrap();
+        forget_token_at(&path).unwrap();
+        assert!(!path.exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
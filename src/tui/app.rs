@@ -0,0 +1,274 @@
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use chrono::Local;
+use ratatui::{
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, Paragraph},
+    Frame,
+};
+use serenity::model::id::GuildId;
+use tokio::sync::mpsc::error::TryRecvError;
+
+use crate::{
+    channel::MessageReceiver,
+    enums::{ChannelMessage, ConnectionState, LogEntry, VoiceEventKind},
+    models::{Roster, VoiceFlags},
+    utils::plural,
+};
+
+/// Events kept in memory, older ones are dropped.
+const MAX_EVENTS: usize = 1000;
+
+pub struct TuiApp {
+    pub bot_name: Option<String>,
+    pub connection: ConnectionState,
+    pub latency: Option<Duration>,
+    pub guilds: Vec<(GuildId, String)>,
+
+    pub events: Vec<LogEntry>,
+    /// Shared with the Discord handlers, see [`crate::discord::DiscordClient`].
+    pub roster: Arc<RwLock<Roster>>,
+
+    /// Number of events scrolled past at the top of the event log.
+    pub scroll: usize,
+
+    /// Messages lost because the UI fell behind, see [`crate::channel`].
+    pub dropped_messages: usize,
+
+    rx: MessageReceiver,
+}
+
+impl TuiApp {
+    pub fn new(rx: MessageReceiver, roster: Arc<RwLock<Roster>>) -> Self {
+        TuiApp {
+            bot_name: None,
+            connection: ConnectionState::default(),
+            latency: None,
+            guilds: vec![],
+
+            events: vec![],
+            roster,
+
+            scroll: 0,
+
+            dropped_messages: 0,
+
+            rx,
+        }
+    }
+
+    /// Applies every pending message. Returns `false` once the Discord
+    /// handler is gone.
+    pub fn drain_messages(&mut self) -> bool {
+        loop {
+            let message = match self.rx.try_recv() {
+                Ok(message) => message,
+                Err(TryRecvError::Empty) => {
+                    let dropped = self.rx.take_dropped();
+                    if dropped > 0 {
+                        self.dropped_messages += dropped;
+                        self.push(LogEntry::Error(format!(
+                            "{} messages dropped while the terminal was not responding",
+                            dropped
+                        )));
+                    }
+                    return true;
+                }
+                Err(TryRecvError::Disconnected) => return false,
+            };
+
+            match message {
+                ChannelMessage::BotConnected(ready) => {
+                    self.bot_name = Some(ready.user.name);
+                }
+                ChannelMessage::GuildAvailable(guild_id, name) => {
+                    match self.guilds.iter_mut().find(|(id, _)| *id == guild_id) {
+                        Some(guild) => guild.1 = name,
+                        None => self.guilds.push((guild_id, name)),
+                    }
+                }
+                ChannelMessage::Voice(event) => {
+                    self.push(LogEntry::Voice(event));
+                }
+                ChannelMessage::Custom(event) => {
+                    self.push(LogEntry::Custom(event));
+                }
+                ChannelMessage::Error(error) => {
+                    self.push(LogEntry::Error(error));
+                }
+                ChannelMessage::Connection(state) => {
+                    self.connection = state;
+                    if state != ConnectionState::Connected {
+                        self.latency = None;
+                    }
+                }
+                ChannelMessage::Latency(latency) => {
+                    self.latency = Some(latency);
+                }
+                ChannelMessage::DebugData(_) => (),
+            }
+        }
+    }
+
+    fn push(&mut self, entry: LogEntry) {
+        self.events.insert(0, entry);
+        self.events.truncate(MAX_EVENTS);
+        if self.scroll > 0 {
+            self.scroll += 1;
+        }
+    }
+
+    pub fn scroll_up(&mut self, lines: usize) {
+        self.scroll = self.scroll.saturating_sub(lines);
+    }
+
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.scroll = (self.scroll + lines).min(self.events.len().saturating_sub(1));
+    }
+
+    pub fn render(&self, frame: &mut Frame) {
+        let [main, status] = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(3), Constraint::Length(1)])
+            .areas(frame.area());
+        let [events, roster_area] = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+            .areas(main);
+
+        // ! Events list
+        let items = self
+            .events
+            .iter()
+            .skip(self.scroll)
+            .map(event_line)
+            .map(ListItem::new)
+            .collect::<Vec<_>>();
+        frame.render_widget(
+            List::new(items).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Discord Events"),
+            ),
+            events,
+        );
+
+        // ! Roster
+        let roster = self.roster.read().unwrap();
+        let mut lines = vec![];
+        for guild in roster.guilds.iter() {
+            lines.push(ListItem::new(Line::from(Span::styled(
+                guild.guild_name.clone(),
+                Style::default().add_modifier(Modifier::BOLD),
+            ))));
+            for channel in guild.channels.iter() {
+                lines.push(ListItem::new(format!(" {}", channel.channel_name)));
+                for member in channel.members.iter() {
+                    lines.push(ListItem::new(format!(
+                        "   {}{}",
+                        member.user_name,
+                        flag_badges(member.flags)
+                    )));
+                }
+            }
+        }
+        frame.render_widget(
+            List::new(lines).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!("In voice ({})", roster.member_count())),
+            ),
+            roster_area,
+        );
+
+        // ! Status line
+        let color = match self.connection {
+            ConnectionState::Connected => Color::Green,
+            ConnectionState::Connecting | ConnectionState::Resuming => Color::Yellow,
+            ConnectionState::Disconnected | ConnectionState::AuthFailed => Color::Red,
+        };
+        let mut connection = format!("● {}", self.connection.label());
+        if let Some(ref name) = self.bot_name {
+            connection.push_str(&format!(" ( {} )", name));
+        }
+        if let Some(latency) = self.latency {
+            connection.push_str(&format!(" {} ms", latency.as_millis()));
+        }
+        let mut status_line = vec![Span::styled(connection, Style::default().fg(color))];
+        if self.dropped_messages > 0 {
+            status_line.push(Span::styled(
+                format!(" | {} dropped", self.dropped_messages),
+                Style::default().fg(Color::Red),
+            ));
+        }
+        status_line.push(Span::raw(format!(
+            " | {} | ↑/↓ scroll | r reconnect | q quit",
+            plural(self.guilds.len(), "guild")
+        )));
+        frame.render_widget(Paragraph::new(Line::from(status_line)), status);
+    }
+}
+
+fn event_line(entry: &LogEntry) -> Line<'static> {
+    match entry {
+        LogEntry::Voice(event) => {
+            let style = match event.kind {
+                VoiceEventKind::Joined | VoiceEventKind::AlreadyIn => {
+                    Style::default().add_modifier(Modifier::BOLD)
+                }
+                VoiceEventKind::Left => Style::default().add_modifier(Modifier::CROSSED_OUT),
+                VoiceEventKind::StartedStreaming => {
+                    Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+                }
+                VoiceEventKind::ServerMuted
+                | VoiceEventKind::ServerDeafened
+                | VoiceEventKind::Suppressed => Style::default().add_modifier(Modifier::ITALIC),
+                _ => Style::default().fg(Color::Gray),
+            };
+            Line::from(vec![
+                Span::styled(
+                    event
+                        .timestamp
+                        .with_timezone(&Local)
+                        .format("%H:%M:%S ")
+                        .to_string(),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::styled(
+                    format!("[{}] ", event.guild_name),
+                    Style::default().fg(Color::Cyan),
+                ),
+                Span::styled(event.description(), style),
+            ])
+        }
+        LogEntry::Custom(event) => Line::from(Span::styled(
+            event.replace(['\n', '\t'], " "),
+            Style::default().fg(Color::DarkGray),
+        )),
+        LogEntry::Error(error) => {
+            Line::from(Span::styled(error.clone(), Style::default().fg(Color::Red)))
+        }
+    }
+}
+
+fn flag_badges(flags: VoiceFlags) -> String {
+    let mut badges = String::new();
+    if flags.self_stream {
+        badges.push_str(" [LIVE]");
+    }
+    if flags.self_video {
+        badges.push_str(" [CAM]");
+    }
+    if flags.self_mute || flags.mute {
+        badges.push_str(" [M]");
+    }
+    if flags.self_deaf || flags.deaf {
+        badges.push_str(" [D]");
+    }
+    badges
+}
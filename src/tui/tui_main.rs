@@ -0,0 +1,103 @@
+use std::{
+    io,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    DefaultTerminal,
+};
+use tokio::runtime::Runtime;
+
+use crate::{
+    audio::AudioPlayer,
+    channel::message_channel,
+    config::Config,
+    discord::{DiscordClient, DiscordEventHandler},
+    rules::RuleSet,
+    token::require_token,
+};
+
+use super::app::TuiApp;
+
+pub fn start_tui(config: Config) -> io::Result<()> {
+    // * Create tokio runtime
+    let rt = Runtime::new().expect("Unable to create Runtime");
+    let _enter = rt.enter();
+
+    // * Load discord token
+    let token = require_token(&config.token);
+
+    // * Create channel, errors go to the log instead of scribbling over the
+    // terminal
+    let (tx, rx) = message_channel(None);
+    let tx = tx.on_terminal();
+
+    let watch_filter = Arc::new(RwLock::new(config.filter));
+    let rules = Arc::new(RwLock::new(RuleSet::load()));
+
+    // * Create Discord thread
+    let notifications = Arc::new(RwLock::new(config.notifications));
+    let audio = AudioPlayer::spawn(config.sounds, Some(tx.clone()));
+    let mut client = DiscordClient::new(config.overrides.record, move || {
+        DiscordEventHandler::new(
+            tx.clone(),
+            watch_filter.clone(),
+            rules.clone(),
+            audio.clone(),
+            notifications.clone(),
+        )
+    });
+    client.start(token);
+
+    // * Run terminal app
+    let mut terminal = ratatui::init();
+    let result = run(
+        &mut terminal,
+        TuiApp::new(rx, client.roster.clone()),
+        &mut client,
+    );
+    ratatui::restore();
+
+    client.stop();
+    client.save_open_sessions();
+    result
+}
+
+fn run(
+    terminal: &mut DefaultTerminal,
+    mut app: TuiApp,
+    client: &mut DiscordClient,
+) -> io::Result<()> {
+    loop {
+        if !app.drain_messages() {
+            return Ok(());
+        }
+        terminal.draw(|frame| app.render(frame))?;
+
+        if !event::poll(Duration::from_millis(100))? {
+            continue;
+        }
+        if let Event::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(())
+                }
+                KeyCode::Char('r') => {
+                    client.reconnect();
+                }
+                KeyCode::Up => app.scroll_up(1),
+                KeyCode::Down => app.scroll_down(1),
+                KeyCode::PageUp => app.scroll_up(10),
+                KeyCode::PageDown => app.scroll_down(10),
+                KeyCode::Home => app.scroll = 0,
+                _ => (),
+            }
+        }
+    }
+}
@@ -1,37 +1,54 @@
+use chrono::TimeDelta;
 use notify_rust::{Notification, Timeout};
-use rodio::{source::Source, Decoder, OutputStream};
-use std::fs::File;
-use std::io::BufReader;
+use std::path::PathBuf;
 
-use crate::enums::NotificationSound;
+use crate::{config::NotificationSettings, enums::NotificationSound};
+
+/// Directory holding the configuration files, `$XDG_CONFIG_HOME/discord_watcher`.
+pub fn config_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_default()
+        .join("discord_watcher")
+}
+
+/// Directory holding the event history, `$XDG_DATA_HOME/discord_watcher`.
+pub fn data_dir() -> PathBuf {
+    dirs::data_dir().unwrap_or_default().join("discord_watcher")
+}
+
+pub fn push_notification(
+    body: &str,
+    settings: NotificationSettings,
+) -> notify_rust::error::Result<()> {
+    if !settings.enabled {
+        return Ok(());
+    }
 
-pub fn push_notification(body: &str) {
     Notification::new()
         .summary("Discord Watcher")
-        .timeout(Timeout::Milliseconds(500))
+        .timeout(Timeout::Milliseconds(settings.timeout_ms))
         .auto_icon()
         .sound_name(&NotificationSound::Reminder.to_str())
         .body(body)
         .finalize()
         .show()
-        .unwrap();
+        .map(|_| ())
+}
+
+/// `count` followed by `noun`, adding an `s` unless there is exactly one.
+pub fn plural(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {}", noun),
+        count => format!("{} {}s", count, noun),
+    }
 }
 
-pub fn play_sound() {
-    // Get a output stream handle to the default physical sound device
-    let (_stream, stream_handle) = OutputStream::try_default().unwrap();
-    // Load a sound from a file, using a path relative to Cargo.toml
-    let file = BufReader::new(File::open("assets/notif_sound.wav").unwrap());
-    // Decode that sound file into a source
-    let source = Decoder::new_wav(file).unwrap();
-    // Reduce the volume by multiplying each sample by a factor (e.g., 0.5 for half volume)
-    let source = source.amplify(0.1);
-    // Play the sound directly on the device
-    match stream_handle.play_raw(source.convert_samples()) {
-        Ok(_) => (),
-        Err(e) => eprintln!("Error when playing sound: {}", e),
+/// Short human readable duration, e.g. `"2 h 05 min"`, `"12 min"` or `"40 s"`.
+pub fn format_duration(duration: TimeDelta) -> String {
+    let seconds = duration.num_seconds().max(0);
+    match (seconds / 3600, seconds / 60 % 60) {
+        (0, 0) => format!("{} s", seconds),
+        (0, minutes) => format!("{} min", minutes),
+        (hours, minutes) => format!("{} h {:02} min", hours, minutes),
     }
-    // The sound plays in a separate audio thread,
-    // so we need to keep the main thread alive while it's playing.
-    std::thread::sleep(std::time::Duration::from_secs(1));
 }